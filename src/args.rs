@@ -1,9 +1,11 @@
 use {
+    crate::*,
     argh::FromArgs,
     std::path::PathBuf,
 };
 
 #[derive(FromArgs)]
+#[argh(help_triggers("-h", "--help", "help"))]
 /// Help you remove duplicate files from your disks
 ///
 ///
@@ -13,12 +15,137 @@ pub struct Args {
     #[argh(switch, short = 'v')]
     pub version: bool,
 
-    /// whether to only handle image files
+    /// only handle image files (same as --type images)
     #[argh(switch, short = 'i')]
     pub only_images: bool,
 
+    /// only handle files of this type: images, videos, audio,
+    /// documents or archives (may be repeated)
+    #[argh(option, short = 't', long = "type")]
+    pub types: Vec<FileType>,
+
+    /// recognize the types of files by their first bytes rather
+    /// than by their extension
+    #[argh(switch)]
+    pub sniff: bool,
+
+    /// only handle files with these extensions, given as a comma
+    /// separated list, for example "blend,xcf" (may be repeated)
+    #[argh(option)]
+    pub ext: Vec<String>,
+
+    /// also analyze the files and directories whose name starts with a dot
+    #[argh(switch)]
+    pub hidden: bool,
+
+    /// don't cross file system boundaries
+    #[argh(switch, short = 'x')]
+    pub one_file_system: bool,
+
+    /// respect the rules of .gitignore, .ignore and .backdownignore files
+    #[argh(switch, short = 'g')]
+    pub gitignore: bool,
+
+    /// glob of the files to analyze, for example "*.jpg" or
+    /// "**/photos/**" (may be repeated)
+    #[argh(option)]
+    pub include: Vec<String>,
+
+    /// glob of the files or directories to ignore, for example "*.tmp"
+    /// or "**/node_modules/**" (may be repeated)
+    #[argh(option)]
+    pub exclude: Vec<String>,
+
+    /// minimal size of analyzed files, for example "10K" or "2MiB"
+    #[argh(option, from_str_fn(parse_file_size))]
+    pub min_size: Option<u64>,
+
+    /// maximal size of analyzed files, for example "2G"
+    #[argh(option, from_str_fn(parse_file_size))]
+    pub max_size: Option<u64>,
+
+    /// only analyze files at most this deep in the analyzed directories
+    /// (the files directly in them being at depth 1)
+    #[argh(option)]
+    pub max_depth: Option<usize>,
+
+    /// only analyze files at least this deep in the analyzed directories
+    #[argh(option)]
+    pub min_depth: Option<usize>,
+
+    /// number of files read at the same time, in the order of their
+    /// inodes, which is faster on rotational disks (default: 1 when
+    /// a rotational disk is detected, 0 meaning all threads in no
+    /// particular order otherwise)
+    #[argh(option)]
+    pub readers: Option<usize>,
+
+    /// number of threads used to hash files
+    #[argh(option)]
+    pub threads: Option<usize>,
+
+    /// compare each file byte per byte with a kept identical one just
+    /// before removing it
+    #[argh(switch)]
+    pub paranoid: bool,
+
+    /// don't use the cache of file hashes
+    #[argh(switch)]
+    pub no_cache: bool,
+
+    /// ignore the existing cache of file hashes, and rebuild it
+    #[argh(switch)]
+    pub rebuild_cache: bool,
+
+    /// a directory whose files are never removed, but whose duplicates
+    /// elsewhere can be (may be repeated)
+    #[argh(option)]
+    pub reference: Vec<PathBuf>,
+
     #[argh(positional)]
     /// where to look for duplicates (will use . if no directory is provided)
-    pub path: Option<PathBuf>,
+    pub paths: Vec<PathBuf>,
 }
 
+/// parse a file size, with an optional unit which may be decimal
+/// (K, M, G, T) or binary (Ki, Mi, Gi, Ti), and an optional B
+/// ("12", "10K", "3.5GB" and "4KiB" are valid)
+pub fn parse_file_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let s = s.strip_suffix(['b', 'B']).unwrap_or(s);
+    let (s, base) = match s.strip_suffix(['i', 'I']) {
+        Some(s) => (s, 1024f64),
+        None => (s, 1000f64),
+    };
+    let (number, exponent) = match s.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&s[..s.len()-1], 1),
+        Some('M') => (&s[..s.len()-1], 2),
+        Some('G') => (&s[..s.len()-1], 3),
+        Some('T') => (&s[..s.len()-1], 4),
+        _ => (s, 0),
+    };
+    let number: f64 = number.trim().parse()
+        .map_err(|_| format!("invalid file size: {:?}", s))?;
+    let size = number * base.powi(exponent);
+    if !size.is_finite() || size < 0.0 {
+        return Err(format!("invalid file size: {:?}", s));
+    }
+    Ok(size as u64)
+}
+
+#[test]
+fn test_parse_file_size() {
+    assert_eq!(parse_file_size("12"), Ok(12));
+    assert_eq!(parse_file_size("10K"), Ok(10_000));
+    assert_eq!(parse_file_size("10k"), Ok(10_000));
+    assert_eq!(parse_file_size("2G"), Ok(2_000_000_000));
+    assert_eq!(parse_file_size("3.5MB"), Ok(3_500_000));
+    assert_eq!(parse_file_size("4KiB"), Ok(4_096));
+    assert_eq!(parse_file_size("1Mi"), Ok(1_048_576));
+    assert!(parse_file_size("K").is_err());
+    assert!(parse_file_size("-3K").is_err());
+    assert!(parse_file_size("big").is_err());
+    assert!(parse_file_size("nan").is_err());
+    assert!(parse_file_size("inf").is_err());
+    assert!(parse_file_size("1e308T").is_err());
+}
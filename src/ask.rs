@@ -2,16 +2,57 @@ use {
     crate::*,
     fnv::FnvHashMap,
     minimad::*,
+    std::{
+        collections::BTreeMap,
+        ffi::OsStr,
+        path::{Path, PathBuf},
+    },
     termimad::*,
 };
 
 const MAX_LISTED_FILES: usize = 5;
 
+/// Ask a question and return the key of the chosen answer, or
+/// `None` when the user hits ctrl-c.
+///
+/// This works like termimad's `Question::ask` but reads the answer
+/// in a way which can be interrupted.
+pub fn ask_question(q: &Question, skin: &MadSkin) -> anyhow::Result<Option<String>> {
+    if let Some(md) = &q.md {
+        skin.print_text(md);
+    }
+    for a in &q.answers {
+        if q.default_answer.as_ref() == Some(&a.key) {
+            mad_print_inline!(skin, "[**$0**] ", a.key);
+        } else {
+            mad_print_inline!(skin, "[$0] ", a.key);
+        }
+        skin.print_text(&a.md);
+    }
+    loop {
+        let input = match read_line()? {
+            Some(input) => input,
+            None => { return Ok(None); }
+        };
+        if input.is_empty() {
+            if let Some(da) = &q.default_answer {
+                return Ok(Some(da.clone()));
+            }
+        }
+        if q.answers.iter().any(|a| a.key == input) {
+            return Ok(Some(input));
+        }
+        println!("answer {:?} not understood", input);
+    }
+}
+
 pub fn ask_on_dirs<'d>(
     dirs_report: &'d DirsReport,
-    dups: &'d [DupSet],
+    dup_report: &'d DupReport,
     skin: &MadSkin,
 ) -> anyhow::Result<RemovalReport<'d>> {
+    let dups = &dup_report.dups;
+    let empty_files = &dup_report.empty_files;
     let mut rr = RemovalReport::default();
     let mut question_idx = 0;
     let mut questions = dirs_report.dup_dirs.len() + dirs_report.brotherhoods.len() + dirs_report.dir_pairs.len();
@@ -19,6 +60,14 @@ pub fn ask_on_dirs<'d>(
     if ask_about_autosolve {
         questions += 1;
     }
+    if !empty_files.is_empty() {
+        questions += 1;
+    }
+    // directories may be asked about when they're already empty or
+    // when the previous questions may stage what they contain
+    if dup_report.empty_dir_count > 0 || (questions > 0 && !dup_report.dirs.is_empty()) {
+        questions += 1;
+    }
 
     static MD: &str = r#"
     I'll now ask you up to *${questions}* questions to determine what files should be removed.\
@@ -108,6 +157,7 @@ pub fn ask_on_dirs<'d>(
             question_idx,
             questions,
             dir_pair,
+            dirs_report.skip_hidden,
             dups,
             skin,
             &mut rr,
@@ -117,6 +167,34 @@ pub fn ask_on_dirs<'d>(
         }
         question_idx += 1;
     }
+    if rr.broken || rr.quit {
+        return Ok(rr);
+    }
+
+    if !empty_files.is_empty() {
+        ask_on_empty_files(
+            question_idx,
+            questions,
+            empty_files,
+            skin,
+            &mut rr,
+        )?;
+        if rr.broken {
+            return Ok(rr);
+        }
+        question_idx += 1;
+    }
+
+    // this question comes last, as directories may become
+    // empty because of the previously staged removals
+    ask_on_empty_dirs(
+        question_idx,
+        questions,
+        &dup_report.dirs,
+        dups,
+        skin,
+        &mut rr,
+    )?;
 
     Ok(rr)
 }
@@ -145,7 +223,14 @@ fn ask_auto_solve<'d>(
     let mut example_names = Vec::new();
     for brotherhood in dirs_report.brotherhoods.iter().filter(|b| b.is_auto_solvable) {
         removable_count += brotherhood.files.len() - 1;
-        removable_len += (brotherhood.files.len() - 1) as u64 * dups[brotherhood.dup_set_idx].file_len;
+        removable_len += brotherhood.files.iter()
+            .map(|&dup_file_idx| DupFileRef {
+                dup_set_idx: brotherhood.dup_set_idx,
+                dup_file_idx,
+            })
+            .filter(|dup_file_ref| dup_file_ref.is_copy_named(dups))
+            .map(|dup_file_ref| dup_file_ref.freed_len(dups))
+            .sum::<u64>();
         skippable_questions += 1;
         if example_names.len() < 2 {
             example_names.push(
@@ -164,14 +249,19 @@ fn ask_auto_solve<'d>(
     expander
         .set("num", question_idx + 1)
         .set("questions", questions)
-        .set("example_1", example_names[0])
-        .set("example_2", example_names[1])
+        .set("example_1", &example_names[0])
+        .set("example_2", &example_names[1])
         .set("skippable_questions", skippable_questions)
         .set("file_count", removable_count)
         .set("size", file_size::fit_4(removable_len));
     skin.print_owning_expander(&expander, &TextTemplate::from(MD_AUTO_SOLVE));
-    Ok(ask!(skin, "Do you want me to automatically stage those copies ?", ('y') {
-        ('y', "**Y**es") => {
+    let mut q = Question::new("Do you want me to automatically stage those copies ?");
+    q.add_answer('y', "**Y**es");
+    q.add_answer('n', "**N**o");
+    q.add_answer('e', "**E**nd staging and quit");
+    q.set_default("y");
+    Ok(match ask_question(&q, skin)?.as_deref() {
+        Some("y") => {
             for brotherhood in dirs_report.brotherhoods.iter().filter(|b| b.is_auto_solvable) {
                 let dup_file_refs = brotherhood.files.iter()
                     .map(|&dup_file_idx| DupFileRef {
@@ -185,21 +275,31 @@ fn ask_auto_solve<'d>(
             }
             true
         }
-        ('n', "**N**o") => {
+        Some("e") => {
+            rr.quit = true;
             false
         }
-        ('e', "**E**nd staging and quit") => {
-            rr.quit = true;
+        Some(_) => false,
+        None => {
+            rr.broken = true;
             false
         }
-    }))
+    })
 }
 
 static MD_DUP_DIR: &str = r#"
 
 ## Staging Question **${num}**/${questions}
-The *${directory}* directory contains **${file_count}** files which are all present elsewhere.\
+The *${directory}* directory (depth ${depth}) contains **${file_count}** files which are all present elsewhere.\
+${hidden
+It also contains **${hidden_count}** hidden files or directories, which won't be removed.\
+}
+${removable
 You can remove the whole directory without losing anything.\
+}
+${linked
+**${linked_count}** of these files have other hard links, which are kept, so removing them frees no space.\
+}
 This would let you gain **${size}**.\
 "#;
 
@@ -234,34 +334,58 @@ fn ask_on_dup_dir<'d>(
         }
     }
     // now we know we can stage the whole directory
-    let removable_len = dup_dir.files.iter()
-        .map(|dup_file_ref| dups[dup_file_ref.dup_set_idx].file_len)
-        .sum();
+    let removable_len = dup_dir.gain(dups);
     let mut expander = OwningTemplateExpander::new();
     expander
         .set("num", question_idx + 1)
         .set("questions", questions)
         .set("directory", dup_dir.path.to_string_lossy())
+        .set("depth", dup_dir.depth)
         .set("file_count", dup_dir.files.len())
         .set("size", file_size::fit_4(removable_len));
+    if dup_dir.hidden_count > 0 {
+        expander.sub("hidden")
+            .set("hidden_count", dup_dir.hidden_count);
+    } else {
+        expander.sub("removable");
+    }
+    let linked_count = dup_dir.files.iter()
+        .filter(|dup_file_ref| dup_file_ref.freed_len(dups) == 0)
+        .count();
+    if linked_count > 0 {
+        expander.sub("linked")
+            .set("linked_count", linked_count);
+    }
     skin.print_owning_expander(&expander, &TextTemplate::from(MD_DUP_DIR));
-    ask!(skin, "What do you want to do with this directory?", ('s') {
-        ('r', "Stage the whole directory for **r**emoval") => {
+    let mut q = Question::new("What do you want to do with this directory?");
+    if dup_dir.hidden_count > 0 {
+        q.add_answer('r', "Stage the non hidden files for **r**emoval");
+    } else {
+        q.add_answer('r', "Stage the whole directory for **r**emoval");
+    }
+    q.add_answer('s', "**S**kip and go to next question");
+    q.add_answer('e', "**E**nd staging phase");
+    q.set_default("s");
+    match ask_question(&q, skin)?.as_deref() {
+        Some("r") => {
             for &file_ref in &dup_dir.files {
                 rr.stage_file(file_ref, dups);
             }
-            rr.staged_dir_removals.push(dup_dir.path);
+            // the directory can't be removed when it still contains hidden files
+            if dup_dir.hidden_count == 0 {
+                rr.staged_dir_removals.push(dup_dir.path);
+            }
         }
-        ('s', "**S**kip and go to next question") => {}
-        ('e', "**E**nd staging phase") => { rr.broken = true; }
-    });
+        Some("e") | None => { rr.broken = true; }
+        _ => {}
+    }
     Ok(())
 }
 
 static MD_BROTHERHOOD: &str = r#"
 
 ## Staging Question **${num}**/${questions}
-The *${parent}* directory contains **${file_count}** identical files, each one of size **${size}**.
+The *${parent}* directory (depth ${depth}) contains **${file_count}** identical files, each one of size **${size}**.
 "#;
 
 // ask for a set of identical files in the same directory
@@ -281,36 +405,40 @@ fn ask_on_brotherhood(
         .set("num", question_idx + 1)
         .set("questions", questions)
         .set("parent", brotherhood.parent.to_string_lossy())
+        .set("depth", brotherhood.depth)
         .set("file_count", brotherhood.files.len())
         .set("size", file_size::fit_4(dup_set.file_len));
     skin.print_owning_expander(&expander, &TextTemplate::from(MD_BROTHERHOOD));
     let mut q = Question::new("What do you want to do with these duplicates?");
 
-    struct F<'f> { idx: usize, name: &'f str }
+    struct F<'f> { idx: usize, name: &'f OsStr }
+    // when there are files of a reference root, only them may be the kept one
+    let has_reference = brotherhood.files.iter().any(|&idx| dup_set.files[idx].reference);
     let mut candidates: Vec<F> = brotherhood.files.iter()
-        .map(|&idx| F{ idx, name: dup_set.files[idx].path.file_name().unwrap().to_str().unwrap() })
+        .filter(|&&idx| !has_reference || dup_set.files[idx].reference)
+        .filter_map(|&idx| dup_set.files[idx].path.file_name().map(|name| F{ idx, name }))
         .collect();
     candidates.sort_by(|a, b| a.name.cmp(b.name));
     for (i, f) in candidates.iter().enumerate() {
         q.add_answer(
             i + 1,
-            format!("keep *{}* and stage other one(s) for removal", f.name),
+            format!("keep *{}* and stage other one(s) for removal", f.name.to_string_lossy()),
         );
     }
     q.add_answer('s', "**S**kip and go to next question");
     q.add_answer('e', "**E**nd staging phase");
     q.set_default("s");
-    match q.ask(skin)?.as_str() {
-        "s" => {}
-        "e" => { rr.broken = true; }
-        a => {
+    match ask_question(&q, skin)?.as_deref() {
+        Some("s") => {}
+        Some("e") | None => { rr.broken = true; }
+        Some(a) => {
             if let Ok(a) = a.parse::<usize>() {
-                if a == 0 {
-                    println!("Options start at 1 - skipping");
+                if a == 0 || a > candidates.len() {
+                    println!("Not a valid option - skipping");
                 } else {
                     let chosen = &candidates[a - 1];
                     for i in 0..brotherhood.files.len() {
-                        if i != chosen.idx {
+                        if brotherhood.files[i] != chosen.idx && !brotherhood.file_ref(i).is_reference(dups) {
                             rr.stage_file(brotherhood.file_ref(i), dups);
                         }
                     }
@@ -321,6 +449,136 @@ fn ask_on_brotherhood(
     Ok(())
 }
 
+static MD_EMPTY_FILES: &str = r#"
+
+## Staging Question **${num}**/${questions}
+**${file_count}** empty files were found in **${dir_count}** directories.
+"#;
+
+/// ask what to do with the empty files, all at once or directory
+/// per directory
+fn ask_on_empty_files<'d>(
+    question_idx: usize,
+    questions: usize,
+    empty_files: &'d [DupFile],
+    skin: &MadSkin,
+    rr: &mut RemovalReport<'d>,
+) -> anyhow::Result<()> {
+    let mut files_per_dir: BTreeMap<&Path, Vec<&Path>> = BTreeMap::new();
+    for file in empty_files {
+        if let Some(parent) = file.path.parent() {
+            files_per_dir.entry(parent).or_default().push(&file.path);
+        }
+    }
+    let mut expander = OwningTemplateExpander::new();
+    expander
+        .set("num", question_idx + 1)
+        .set("questions", questions)
+        .set("file_count", empty_files.len())
+        .set("dir_count", files_per_dir.len());
+    skin.print_owning_expander(&expander, &TextTemplate::from(MD_EMPTY_FILES));
+    let mut q = Question::new("What do you want to do with these empty files?");
+    q.add_answer('a', "Stage **a**ll of them for removal");
+    q.add_answer('d', "Choose **d**irectory per directory");
+    q.add_answer('s', "**S**kip them");
+    q.add_answer('e', "**E**nd staging phase");
+    q.set_default("s");
+    match ask_question(&q, skin)?.as_deref() {
+        Some("a") => {
+            rr.staged_empty_files.extend(files_per_dir.into_values().flatten());
+        }
+        Some("d") => {
+            for (dir, files) in files_per_dir {
+                mad_print_inline!(
+                    skin,
+                    "The *$0* directory contains **$1** empty files.\n",
+                    dir.to_string_lossy(),
+                    files.len(),
+                );
+                let mut q = Question::new("What do you want to do with them?");
+                q.add_answer('r', "Stage them for **r**emoval");
+                q.add_answer('s', "**S**kip and go to next directory");
+                q.add_answer('e', "**E**nd staging phase");
+                q.set_default("s");
+                match ask_question(&q, skin)?.as_deref() {
+                    Some("r") => {
+                        rr.staged_empty_files.extend(files);
+                    }
+                    Some("e") | None => {
+                        rr.broken = true;
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Some("e") | None => {
+            rr.broken = true;
+        }
+        _ => {}
+    }
+    mad_print_inline!(
+        skin,
+        " -> currently staged: **$0** empty files\n",
+        rr.staged_empty_files.len(),
+    );
+    Ok(())
+}
+
+static MD_EMPTY_DIRS: &str = r#"
+
+## Staging Question **${num}**/${questions}
+**${dir_count}** directories are empty, or would be after the staged removals (for example *${example}*).
+"#;
+
+/// ask whether to remove the directories which are empty or would
+/// be once the staged files are removed
+fn ask_on_empty_dirs<'d>(
+    question_idx: usize,
+    questions: usize,
+    dirs: &'d [PathBuf],
+    dups: &'d [DupSet],
+    skin: &MadSkin,
+    rr: &mut RemovalReport<'d>,
+) -> anyhow::Result<()> {
+    let empty_dirs = find_empty_dirs(dirs, dups, rr);
+    if empty_dirs.is_empty() {
+        return Ok(());
+    }
+    let mut expander = OwningTemplateExpander::new();
+    expander
+        .set("num", question_idx + 1)
+        .set("questions", questions)
+        .set("dir_count", empty_dirs.len())
+        .set("example", empty_dirs[0].to_string_lossy());
+    skin.print_owning_expander(&expander, &TextTemplate::from(MD_EMPTY_DIRS));
+    loop {
+        let mut q = Question::new("What do you want to do with these directories?");
+        q.add_answer('r', "Stage them for **r**emoval");
+        q.add_answer('l', "**L**ist them");
+        q.add_answer('s', "**S**kip them");
+        q.set_default("s");
+        match ask_question(&q, skin)?.as_deref() {
+            Some("r") => {
+                rr.staged_dir_removals.extend(&empty_dirs);
+                mad_print_inline!(
+                    skin,
+                    " -> currently staged: **$0** directories\n",
+                    rr.staged_dir_removals.len(),
+                );
+            }
+            Some("l") => {
+                for dir in &empty_dirs {
+                    mad_print_inline!(skin, " *$0*\n", dir.to_string_lossy());
+                }
+                continue;
+            }
+            _ => {}
+        }
+        return Ok(());
+    }
+}
+
 static MD_DIR_PAIR: &str = r#"
 
 ## Staging Question **${num}**/${questions}
@@ -328,7 +586,8 @@ Left and right directories have **${file_count}** common files for a total dupli
 |-:|:-:|:-:|
 | |left|right|
 |-:|:-:|:-:|
-|directory|*${left_path}*|*${right_path}*|
+|directory|*${left_path}*${left_reference}|*${right_path}*${right_reference}|
+|depth|${left_depth}|${right_depth}|
 ${common_files
 |common files|${file_count}|${file_count}|
 }
@@ -345,6 +604,7 @@ fn ask_on_dir_pair(
     question_idx: usize,
     questions: usize,
     dir_pair: &DirPair,
+    skip_hidden: bool,
     dups: &[DupSet],
     skin: &MadSkin,
     rr: &mut RemovalReport,
@@ -355,7 +615,12 @@ fn ask_on_dir_pair(
     let (mut removable_left_count, mut removable_right_count) = (0, 0);
     let mut removable_pairs: Vec<FilePair> = Vec::new();
     let mut removable_len: u64 = 0;
+    // files of reference roots are never proposed for removal
+    let mut left_is_reference = false;
+    let mut right_is_reference = false;
     for file_pair in &dir_pair.file_pairs {
+        left_is_reference |= file_pair.left_ref().is_reference(dups);
+        right_is_reference |= file_pair.right_ref().is_reference(dups);
         let removed_left = rr.staged_removals.contains(&file_pair.left_ref());
         let removed_right = rr.staged_removals.contains(&file_pair.right_ref());
         if removed_left {
@@ -377,13 +642,16 @@ fn ask_on_dir_pair(
         mad_print_inline!(skin, "*skipping question because of previously staged removals*\n");
         return Ok(());
     }
-    let left_dir_count = dir_pair.key.left_dir.read_dir()?.count();
+    if left_is_reference && right_is_reference {
+        return Ok(());
+    }
+    let (left_dir_count, _) = count_dir_entries(dir_pair.key.left_dir, skip_hidden)?;
     if left_dir_count < removed_left_count + removable_left_count {
         println!("skipping question because some files were removed on disk");
         return Ok(());
     }
     let left_other_count = left_dir_count  - removed_left_count - removable_left_count;
-    let right_dir_count = dir_pair.key.right_dir.read_dir()?.count();
+    let (right_dir_count, _) = count_dir_entries(dir_pair.key.right_dir, skip_hidden)?;
     if right_dir_count < removed_right_count + removable_right_count {
         println!("skipping question because some files were removed on disk");
         return Ok(());
@@ -397,6 +665,10 @@ fn ask_on_dir_pair(
         .set("removable_len", file_size::fit_4(removable_len))
         .set("left_path", dir_pair.key.left_dir.to_string_lossy())
         .set("right_path", dir_pair.key.right_dir.to_string_lossy())
+        .set("left_depth", dir_pair.left_depth)
+        .set("right_depth", dir_pair.right_depth)
+        .set("left_reference", if left_is_reference { " (reference)" } else { "" })
+        .set("right_reference", if right_is_reference { " (reference)" } else { "" })
         .set("removed_left_count",  removed_left_count)
         .set("removed_right_count", removed_right_count)
         .set("left_other_count", left_other_count)
@@ -412,24 +684,34 @@ fn ask_on_dir_pair(
         expander.sub("common_files");
     }
     skin.print_owning_expander(&expander, &TextTemplate::from(MD_DIR_PAIR));
-    ask!(skin, "What do you want to do here?", ('s') {
-        ('l', "Stage **l**eft files for removal") => {
+    let mut q = Question::new("What do you want to do here?");
+    if !left_is_reference {
+        q.add_answer('l', "Stage **l**eft files for removal");
+    }
+    if !right_is_reference {
+        q.add_answer('r', "Stage **r**ight files for removal");
+    }
+    q.add_answer('s', "**S**kip and go to next question");
+    q.add_answer('e', "**E**nd staging phase");
+    q.set_default("s");
+    match ask_question(&q, skin)?.as_deref() {
+        Some("l") => {
             for file_pair in removable_pairs {
                 rr.stage_file(file_pair.left_ref(), dups);
             }
         }
-        ('r', "Stage **r**ight files for removal") => {
+        Some("r") => {
             for file_pair in removable_pairs {
                 rr.stage_file(file_pair.right_ref(), dups);
             }
         }
-        ('s', "**S**kip and go to next question") => {
-            println!("skipped");
-        }
-        ('e', "**E**nd staging phase") => {
+        Some("e") | None => {
             rr.broken = true;
         }
-    });
+        _ => {
+            println!("skipped");
+        }
+    }
     Ok(())
 }
 
@@ -3,19 +3,23 @@ use {
     fnv::FnvHashMap,
     std::{
         cmp::{Ord, Ordering, Reverse},
+        ffi::OsStr,
+        io,
         path::Path,
     },
 };
 
 #[derive(Debug)]
 pub struct DirsReport<'d> {
+    /// whether hidden files were skipped during the analysis
+    pub skip_hidden: bool,
     pub dup_dirs: Vec<DupDir<'d>>,
     pub brotherhoods: Vec<Brotherhood<'d>>,
     pub auto_solvable_brotherhoods_count: usize,
     pub dir_pairs: Vec<DirPair<'d>>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct DirPairKey<'d> {
     pub left_dir: &'d Path,
     pub right_dir: &'d Path,
@@ -25,6 +29,9 @@ pub struct DirPairKey<'d> {
 pub struct DirPair<'d> {
     pub key: DirPairKey<'d>,
     pub file_pairs: Vec<FilePair>,
+    /// depths of the left and right directories in their roots
+    pub left_depth: usize,
+    pub right_depth: usize,
 }
 
 /// a brotherhood gather duplicates having the same parent
@@ -33,6 +40,9 @@ pub struct Brotherhood<'d> {
 
     pub parent: &'d Path,
 
+    /// depth of the parent in its root, 0 for the root itself
+    pub depth: usize,
+
     pub dup_set_idx: usize,
 
     /// file indexes
@@ -45,11 +55,40 @@ pub struct Brotherhood<'d> {
     pub is_auto_solvable: bool,
 }
 
-/// a directory which contains only duplicates
+/// a directory which contains only duplicates (and maybe
+/// hidden files when they weren't analyzed)
 #[derive(Debug)]
 pub struct DupDir<'d> {
     pub path: &'d Path,
+    /// depth of the directory in its root, 0 for the root itself
+    pub depth: usize,
     pub files: Vec<DupFileRef>,
+    /// number of hidden files or directories, which weren't
+    /// analyzed and will be kept
+    pub hidden_count: usize,
+}
+
+/// tell whether a file or directory is hidden, according
+/// to the unix convention
+pub fn is_hidden(name: &OsStr) -> bool {
+    name.to_string_lossy().starts_with('.')
+}
+
+/// count the entries of a directory, hidden entries being
+/// counted apart when they weren't analyzed.
+/// Return the counts of visible and hidden entries.
+pub fn count_dir_entries(path: &Path, skip_hidden: bool) -> io::Result<(usize, usize)> {
+    let mut visible_count = 0;
+    let mut hidden_count = 0;
+    for e in path.read_dir()? {
+        let e = e?;
+        if skip_hidden && is_hidden(&e.file_name()) {
+            hidden_count += 1;
+        } else {
+            visible_count += 1;
+        }
+    }
+    Ok((visible_count, hidden_count))
 }
 
 impl<'d> Brotherhood<'d> {
@@ -67,8 +106,12 @@ impl<'d> Brotherhood<'d> {
             dup_file_idx: self.files[i],
         }
     }
+    /// size freed by keeping only one of the files, the one
+    /// whose removal would free the least
     pub fn gain(&self, dups: &[DupSet]) -> u64 {
-        (self.files.len() - 1) as u64 * dups[self.dup_set_idx].file_len
+        let dup_set = &dups[self.dup_set_idx];
+        let freed_lens = self.files.iter().map(|&idx| dup_set.freed_len(idx));
+        freed_lens.clone().sum::<u64>() - freed_lens.min().unwrap_or(0)
     }
 }
 
@@ -98,13 +141,37 @@ impl<'d> DirPair<'d> {
     pub fn new(
         key: DirPairKey<'d>,
         file_pairs: Vec<FilePair>,
+        dups: &[DupSet],
     ) -> Self {
-        Self { key, file_pairs }
+        // file pairs can't be empty, and all their files
+        // are in the same two directories
+        let file_pair = file_pairs[0];
+        let left_depth = dups[file_pair.dup_set_idx].files[file_pair.left_file_idx].depth - 1;
+        let right_depth = dups[file_pair.dup_set_idx].files[file_pair.right_file_idx].depth - 1;
+        Self { key, file_pairs, left_depth, right_depth }
+    }
+    /// size of the duplicates of one of the directories
+    pub fn gain(&self, dups: &[DupSet]) -> u64 {
+        self.file_pairs.iter()
+            .map(|file_pair| dups[file_pair.dup_set_idx].file_len)
+            .sum()
+    }
+}
+
+impl<'d> DupDir<'d> {
+    /// size freed by removing the files of the directory
+    pub fn gain(&self, dups: &[DupSet]) -> u64 {
+        self.files.iter()
+            .map(|dup_file_ref| dup_file_ref.freed_len(dups))
+            .sum()
     }
 }
 
 impl<'d> DirsReport<'d> {
-    pub fn compute(dups: &'d[DupSet]) -> anyhow::Result<Self> {
+    pub fn compute(
+        dups: &'d[DupSet],
+        options: &ScanOptions,
+    ) -> anyhow::Result<Self> {
         let mut brotherhoods = Vec::new();
         let mut auto_solvable_brotherhoods_count = 0;
         let mut dp_map: FnvHashMap<DirPairKey, Vec<FilePair>> = FnvHashMap::default();
@@ -138,12 +205,13 @@ impl<'d> DirsReport<'d> {
                             .entry(a_parent)
                             .or_insert_with(|| Brotherhood {
                                 parent: a_parent,
+                                depth: a.depth - 1,
                                 dup_set_idx,
                                 files: Vec::new(),
                                 is_auto_solvable: false,
                             })
                             .maybe_add_files(left_file_idx, right_file_idx);
-                    } else {
+                    } else if !(a.reference && b.reference) {
                         // dir_pair
                         dp_map.entry(dpk)
                             .or_default()
@@ -156,6 +224,10 @@ impl<'d> DirsReport<'d> {
                 }
             }
             for (_, mut brotherhood) in brotherhood_per_parent.drain() {
+                if brotherhood.files.iter().all(|&idx| dup.files[idx].reference) {
+                    // nothing can be removed in a reference root
+                    continue;
+                }
                 let copy_count = brotherhood.files
                     .iter()
                     .map(|&dup_file_idx| DupFileRef {
@@ -185,22 +257,30 @@ impl<'d> DirsReport<'d> {
                 // the dups by comparing dup dirs
                 continue;
             }
-            let total_child_count = path.read_dir()?.count();
-            if total_child_count == files.len() {
-                dup_dirs.push(DupDir { path, files });
+            if files.iter().any(|file_ref| file_ref.is_reference(dups)) {
+                // we never propose to remove a reference directory
+                continue;
+            }
+            // hidden entries aren't compared when they weren't analyzed
+            let (child_count, hidden_count) = count_dir_entries(path, options.skip_hidden)?;
+            if child_count == files.len() {
+                let depth = files[0].depth(dups) - 1;
+                dup_dirs.push(DupDir { path, depth, files, hidden_count });
             }
         }
 
-        // ordering
-        dup_dirs.sort_by_key(|dd| Reverse(dd.files.len()));
-        brotherhoods.sort_by_key(|b| Reverse(b.gain(dups)));
+        // ordering, ties being broken by size then path so that the
+        // questions are always asked in the same order
+        dup_dirs.sort_by_key(|dd| (Reverse(dd.files.len()), Reverse(dd.gain(dups)), dd.path));
+        brotherhoods.sort_by_key(|b| (Reverse(b.gain(dups)), b.parent, b.dup_set_idx));
         let mut dir_pairs: Vec<_> = dp_map
             .drain()
-            .map(|(key, file_pairs)| DirPair::new(key, file_pairs))
+            .map(|(key, file_pairs)| DirPair::new(key, file_pairs, dups))
             .collect();
-        dir_pairs.sort_by_key(|dp| Reverse(dp.file_pairs.len()));
+        dir_pairs.sort_by_key(|dp| (Reverse(dp.file_pairs.len()), Reverse(dp.gain(dups)), dp.key));
 
         Ok(Self {
+            skip_hidden: options.skip_hidden,
             dup_dirs,
             brotherhoods,
             auto_solvable_brotherhoods_count,
@@ -0,0 +1,26 @@
+use {
+    std::path::Path,
+};
+
+/// Tell whether the file or directory is on a rotational disk (an HDD),
+/// on which reading several files at the same time makes the heads
+/// move back and forth.
+///
+/// Return `None` when it can't be determined.
+#[cfg(target_os = "linux")]
+pub fn is_rotational(path: &Path) -> Option<bool> {
+    use std::{fs, os::unix::fs::MetadataExt};
+    let dev = fs::metadata(path).ok()?.dev();
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    let block_dir = Path::new("/sys/dev/block").join(format!("{}:{}", major, minor));
+    // partitions have no queue directory, it's the one of their disk
+    let flag = fs::read_to_string(block_dir.join("queue/rotational"))
+        .or_else(|_| fs::read_to_string(block_dir.join("../queue/rotational")))
+        .ok()?;
+    Some(flag.trim() == "1")
+}
+#[cfg(not(target_os = "linux"))]
+pub fn is_rotational(_path: &Path) -> Option<bool> {
+    None
+}
@@ -1,16 +1,35 @@
 use {
+    crate::*,
     lazy_regex::*,
     std::{
+        borrow::Cow,
+        cmp::Reverse,
         collections::HashSet,
         path::{Path, PathBuf},
     },
 };
 
 
-// TODO virer et utiliser PathBuf directement ?
 #[derive(Debug)]
 pub struct DupFile {
     pub path: PathBuf,
+    /// index of the analyzed root the file was found in
+    pub root_idx: usize,
+    /// whether the file is in a reference root, and thus
+    /// must never be removed
+    pub reference: bool,
+    /// depth of the file in its root, 1 for the files
+    /// directly in the root
+    pub depth: usize,
+    /// the inode number of the file, used to read files in their
+    /// physical order (0 when unknown)
+    pub inode: u64,
+    /// the type of the file, detected from its content when
+    /// sniffing, or from its extension
+    pub file_type: Option<FileType>,
+    /// whether the file has other hard links, which are never removed
+    /// with it, in which case removing it frees nothing
+    pub has_other_links: bool,
     // pub staged_for_removal: bool,
 }
 
@@ -21,48 +40,98 @@ pub struct DupSet {
     pub file_len: u64,
 }
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq,)]
+impl DupSet {
+    /// the type of the files of the set, if known
+    pub fn file_type(&self) -> Option<FileType> {
+        self.files.iter().find_map(|file| file.file_type)
+    }
+    /// the size freed on disk by removing the file, which is nothing
+    /// when it has other hard links
+    pub fn freed_len(&self, dup_file_idx: usize) -> u64 {
+        if self.files[dup_file_idx].has_other_links {
+            0
+        } else {
+            self.file_len
+        }
+    }
+    /// the size freed by removing all the files which may be removed,
+    /// that is all but one when none of them is in a reference root
+    pub fn removable_len(&self) -> u64 {
+        let freed_lens = (0..self.files.len())
+            .filter(|&idx| !self.files[idx].reference)
+            .map(|idx| self.freed_len(idx));
+        if self.files.iter().any(|file| file.reference) {
+            freed_lens.sum()
+        } else {
+            // the kept file is the one whose removal would free the least
+            freed_lens.clone().sum::<u64>() - freed_lens.min().unwrap_or(0)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct DupFileRef {
     pub dup_set_idx: usize,
     pub dup_file_idx: usize,
 }
 
 impl DupFile {
-    pub fn new(path: PathBuf) -> Self {
+    pub fn new(path: PathBuf, root_idx: usize, reference: bool, depth: usize) -> Self {
         Self {
             path,
+            root_idx,
+            reference,
+            depth,
+            inode: 0,
+            file_type: None,
+            has_other_links: false,
             //staged_for_removal: false,
         }
     }
 }
 
+/// return the kept file which best represents the set, preferring
+/// files of reference roots, then the shortest paths
 pub fn reference_file<'a>(
     dup_set_idx: usize,
     dup_set: &'a DupSet,
     staged_removals: &HashSet<DupFileRef>,
 ) -> Option<&'a Path> {
-    let mut best: Option<&Path> = None;
+    let mut best: Option<&DupFile> = None;
     for (dup_file_idx, file) in dup_set.files.iter().enumerate() {
-        let path = &file.path;
         let dup_file_ref = DupFileRef { dup_set_idx, dup_file_idx };
         if staged_removals.contains(&dup_file_ref) {
             continue;
         }
         if let Some(previous) = best {
-            if previous.to_string_lossy().len() > path.to_string_lossy().len() {
-                best = Some(path);
+            if (file.reference, Reverse(file.path.to_string_lossy().len()))
+                > (previous.reference, Reverse(previous.path.to_string_lossy().len()))
+            {
+                best = Some(file);
             }
         } else {
-            best = Some(path);
+            best = Some(file);
         }
     }
-    best
+    best.map(|file| file.path.as_path())
 }
 
 impl DupFileRef {
     pub fn path(self, dups: &[DupSet]) -> &Path {
         &dups[self.dup_set_idx].files[self.dup_file_idx].path
     }
+    /// depth of the file in its root
+    pub fn depth(self, dups: &[DupSet]) -> usize {
+        dups[self.dup_set_idx].files[self.dup_file_idx].depth
+    }
+    /// the size freed on disk by removing the file
+    pub fn freed_len(self, dups: &[DupSet]) -> u64 {
+        dups[self.dup_set_idx].freed_len(self.dup_file_idx)
+    }
+    /// tells whether the file is in a reference root
+    pub fn is_reference(self, dups: &[DupSet]) -> bool {
+        dups[self.dup_set_idx].files[self.dup_file_idx].reference
+    }
     pub fn file_name(self, dups:&[DupSet]) -> String {
         self.path(dups)
             .file_name()
@@ -73,7 +142,7 @@ impl DupFileRef {
     }
     /// get the file name when the file has a name like "thing (3).jpg"
     /// or "thing (3rd copy).png"
-    pub fn copy_name(self, dups:&[DupSet]) -> Option<&str> {
+    pub fn copy_name(self, dups:&[DupSet]) -> Option<Cow<'_, str>> {
         copy_name(self.path(dups))
     }
     /// tells whether the file has a name like "thing (3).jpg"
@@ -84,10 +153,11 @@ impl DupFileRef {
 }
 
 /// get the name if this path is of a "copy" file, that is an usual name for a copy
-pub fn copy_name(path: &Path) -> Option<&str> {
+/// (the name is lossily converted when it's not valid UTF-8)
+pub fn copy_name(path: &Path) -> Option<Cow<'_, str>> {
     path
         .file_name()
-        .and_then(std::ffi::OsStr::to_str)
+        .map(|n| n.to_string_lossy())
         .filter(|n| regex_is_match!(r#"(?x)
             .+
             \((
@@ -2,28 +2,83 @@ use {
     anyhow::Result,
     crossbeam::channel,
     crate::*,
-    fnv::FnvHashMap,
+    fnv::{FnvHashMap, FnvHashSet},
     minimad::*,
     rayon::{
-        prelude::ParallelIterator,
-        iter::ParallelBridge,
+        prelude::*,
     },
     std::{
-        cmp::Reverse,
-        fs,
-        path::PathBuf,
+        hash::Hash,
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
     },
     termimad::*,
 };
 
+/// what the walker didn't look at
+#[derive(Debug, Default)]
+pub struct WalkStats {
+    /// number of directories excluded by a glob
+    pub excluded_dirs: usize,
+    /// number of files excluded, or not included, by a glob
+    pub excluded_files: usize,
+    /// number of files and directories ignored because of
+    /// the rules of ignore files
+    pub ignored: usize,
+    /// number of files too small or too big
+    pub size_filtered: usize,
+    /// number of directories not entered because their
+    /// files would have been too deep
+    pub too_deep_dirs: usize,
+    /// directories which weren't entered because they're
+    /// on another file system
+    pub skipped_mount_points: Vec<PathBuf>,
+}
+
 #[derive(Default)]
 pub struct DupReport {
+    /// the analyzed directories
+    pub roots: Vec<Root>,
     pub dups: Vec<DupSet>,
+    /// the empty files, which aren't considered as duplicates
+    /// (files of reference roots aren't included)
+    pub empty_files: Vec<DupFile>,
+    /// the walked directories which aren't roots nor in reference
+    /// roots, deepest first. They may be proposed for removal when
+    /// they're empty
+    pub dirs: Vec<PathBuf>,
+    /// number of directories found empty, or containing only
+    /// empty directories
+    pub empty_dir_count: usize,
+    /// number of files found
     pub seen: usize,
+    pub seen_per_root: Vec<usize>,
+    pub walk_stats: WalkStats,
+    /// number of files which weren't analyzed because they're
+    /// hard links to already found files
+    pub hard_link_count: usize,
+    /// size which would be taken by the hard links if they were copies
+    pub hard_link_len_sum: u64,
+    /// number of files having several hard links
+    pub hard_linked_file_count: usize,
+    /// number of files which had to be fully hashed because
+    /// their size and partial hash weren't unique
+    pub hashed: usize,
+    /// number of hashes which were found in the cache
+    pub cached: usize,
     /// number of files which could be removed
     /// when keeping one of each set
     pub duplicate_count: usize,
     pub duplicate_len_sum: u64,
+    /// the files and directories which couldn't be read, either
+    /// during the walk or when hashing
+    pub errors: Vec<ScanError>,
+    /// whether the user stopped the analysis, in which case
+    /// only part of the duplicates were found
+    pub interrupted: bool,
 }
 
 impl DupReport {
@@ -31,95 +86,182 @@ impl DupReport {
         self.dups.len()
     }
     pub fn build(
-        root: PathBuf,
-        only_images: bool,
+        roots: Vec<Root>,
+        options: &ScanOptions,
+        cache: Option<&HashCache>,
     ) -> Result<Self> {
-        let (s_matching_files, r_matching_files) = channel::unbounded();
-        let (s_hashed_files, r_hashed_files) = channel::unbounded::<(PathBuf, FileHash)>();
-        let file_generator = std::thread::spawn(move||{
-            let mut dirs = Vec::new();
-            dirs.push(root);
-            while let Some(dir) = dirs.pop() {
-                if let Ok(entries) = fs::read_dir(&dir) {
-                    for e in entries.flatten() {
-                        let path = e.path();
-                        let name = match path.file_name().and_then(|s| s.to_str()) {
-                            Some(s) => s,
-                            None => { continue; },
+        let (s_matching_files, r_matching_files) = channel::unbounded::<FoundFile>();
+        let progress = Arc::new(Progress::default());
+        let progress_display = ProgressDisplay::start(Arc::clone(&progress));
+        let walker = Walker::new(&roots, options, &progress, s_matching_files);
+
+        // files are grouped by size: a file whose size is unique can't
+        // have a duplicate, so there's no need to read it
+        let mut size_map: FnvHashMap<u64, Vec<DupFile>> = FnvHashMap::default();
+        let mut seen = 0;
+        let mut seen_per_root = vec![0; roots.len()];
+        // Paths sharing an inode are hard links to the same file: only one
+        // of them (preferably in a reference root, then the first by path)
+        // is kept for the analysis.
+        // The value is the index of the kept file in its size group.
+        let mut inodes: FnvHashMap<InodeId, usize> = FnvHashMap::default();
+        let mut linked_inodes: FnvHashSet<InodeId> = FnvHashSet::default();
+        let mut hard_link_count = 0;
+        let mut hard_link_len_sum = 0;
+        // the walk runs in other threads while the found files are grouped
+        let walk_result = crossbeam::scope(|scope| {
+            let walk = scope.spawn(|_| walker.walk());
+            for (file, len, inode_id) in r_matching_files.iter() {
+                seen_per_root[file.root_idx] += 1;
+                seen += 1;
+                let group = size_map.entry(len).or_default();
+                if let Some(inode_id) = inode_id {
+                    if let Some(&idx) = inodes.get(&inode_id) {
+                        hard_link_count += 1;
+                        hard_link_len_sum += len;
+                        linked_inodes.insert(inode_id);
+                        let kept = &group[idx];
+                        let replaces_kept = if file.reference == kept.reference {
+                            file.path < kept.path
+                        } else {
+                            file.reference
                         };
-                        if name.starts_with('.') {
-                            continue;
-                        }
-                        if let Ok(md) = path.symlink_metadata() {
-                            if md.is_dir() {
-                                // we add the directory to the channel of dirs needing processing
-                                dirs.push(path);
-                                continue;
-                            }
-                            if md.is_file() {
-                                if only_images {
-                                    let ext = match path.extension().and_then(|s| s.to_str()) {
-                                        Some(s) => s,
-                                        None => { continue; },
-                                    };
-                                    if !ext::is_image(ext) {
-                                        continue;
-                                    }
-                                }
-                                s_matching_files.send(path).unwrap();
-                            }
+                        if replaces_kept {
+                            group[idx] = file;
                         }
+                        continue;
                     }
+                    inodes.insert(inode_id, group.len());
                 }
+                group.push(file);
             }
-        });
+            walk.join().unwrap()
+        }).unwrap();
+        let WalkResult { stats: walk_stats, mut errors, mut removable_dirs } = walk_result;
 
-        // parallel computation of the hashes
-        r_matching_files.into_iter().par_bridge()
-            .for_each_with(s_hashed_files, |s, path| {
-                if let Ok(hash) = FileHash::new(&path) {
-                    s.send((path, hash)).unwrap();
+        // A directory is empty when all its entries are empty directories.
+        // Deepest directories are checked first so that their parents
+        // know how many of their entries are empty.
+        removable_dirs.sort_by(|(a, _), (b, _)| {
+            b.components().count().cmp(&a.components().count())
+                .then_with(|| a.cmp(b))
+        });
+        let mut empty_children: FnvHashMap<PathBuf, usize> = FnvHashMap::default();
+        let mut empty_dir_count = 0;
+        for (dir, entry_count) in &removable_dirs {
+            if empty_children.get(dir).copied().unwrap_or(0) == *entry_count {
+                empty_dir_count += 1;
+                if let Some(parent) = dir.parent() {
+                    *empty_children.entry(parent.to_path_buf()).or_default() += 1;
                 }
-            });
+            }
+        }
+        let dirs = removable_dirs.into_iter().map(|(dir, _)| dir).collect();
 
-        let mut map: FnvHashMap<FileHash, Vec<DupFile>> = FnvHashMap::default();
-        let mut seen = 0;
-        r_hashed_files.iter()
-            .for_each(|(path, hash)| {
-                let e = map.entry(hash).or_default();
-                e.push(DupFile::new(path));
-                seen += 1;
-            });
+        // empty files don't need to be hashed, they're
+        // proposed for removal on their own
+        let mut empty_files: Vec<DupFile> = size_map.remove(&0)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|file| !file.reference)
+            .collect();
+        empty_files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        // The files of the same size are split in groups according to
+        // a partial hash of their first and last blocks, so that most
+        // different files don't have to be read entirely.
+        // Small files are read entirely by the partial hash anyway, so
+        // they skip this step.
+        let (small_groups, big_groups): (Vec<_>, Vec<_>) = size_map
+            .into_iter()
+            .filter(|(_, files)| files.len() > 1)
+            .partition(|(len, _)| *len <= 2 * PARTIAL_HASH_BLOCK_SIZE);
+        progress.start_partial_hashing(
+            big_groups.iter().map(|(_, files)| files.len()).sum()
+        );
+        let mut candidate_groups = small_groups;
+        candidate_groups.extend(
+            split_groups(big_groups, options.readers, &mut errors, |path, len| {
+                let hash = PartialHash::new(path, len);
+                progress.add_partially_hashed();
+                hash
+            })
+        );
+        progress.start_hashing(
+            candidate_groups.iter().map(|(_, files)| files.len()).sum(),
+            candidate_groups.iter().map(|(len, files)| len * files.len() as u64).sum(),
+        );
 
-        file_generator.join().unwrap();
+        // parallel computation of the full hashes. Big files are also
+        // hashed by several threads, unless reads must be in order
+        let parallel = options.readers.is_none();
+        let map = split_groups(candidate_groups, options.readers, &mut errors, |path, len| {
+            let hash = match cache {
+                Some(cache) => cache.file_hash(path, parallel),
+                None => FileHash::new(path, parallel),
+            };
+            progress.add_hashed(len);
+            hash
+        });
+        progress_display.stop();
+        let hashed = progress.files_hashed.load(Ordering::Relaxed);
+        let interrupted = is_interrupted();
+        let cached = cache.map_or(0, HashCache::hit_count);
 
         let mut dups = Vec::new();
         let mut duplicate_count = 0;
         let mut duplicate_len_sum = 0;
-        for (_hash, files) in map.drain() {
-            if files.len() < 2 {
+        for (file_len, files) in map {
+            // files of reference roots are kept, so the other ones are
+            // all removable when there's at least one of them
+            let reference_count = files.iter().filter(|file| file.reference).count();
+            let removable_count = if reference_count > 0 {
+                files.len() - reference_count
+            } else {
+                files.len() - 1
+            };
+            if removable_count == 0 {
                 continue;
             }
-            if let Ok(md) = fs::metadata(&files[0].path) {
-                duplicate_count += files.len() - 1;
-                let file_len = md.len();
-                if file_len > 0 {
-                    duplicate_len_sum += (files.len() - 1) as u64 * file_len;
-                    dups.push(DupSet {
-                        files,
-                        file_len,
-                    });
-                }
-            }
+            duplicate_count += removable_count;
+            let mut files = files;
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            set_file_types(&mut files, options.sniff);
+            let dup_set = DupSet {
+                files,
+                file_len,
+            };
+            duplicate_len_sum += dup_set.removable_len();
+            dups.push(dup_set);
         }
 
-        dups.sort_by_key(|dup| Reverse(dup.files.len()));
+        // biggest sets first, then biggest files, then by path, so that
+        // two analyses of the same directories give the same report
+        dups.sort_by(|a, b| {
+            b.files.len().cmp(&a.files.len())
+                .then(b.file_len.cmp(&a.file_len))
+                .then_with(|| a.files[0].path.cmp(&b.files[0].path))
+        });
+        errors.sort_by(|a, b| a.path.cmp(&b.path));
 
         Ok(Self{
+            roots,
             dups,
+            empty_files,
+            dirs,
+            empty_dir_count,
             seen,
+            seen_per_root,
+            walk_stats,
+            hard_link_count,
+            hard_link_len_sum,
+            hard_linked_file_count: linked_inodes.len(),
+            hashed,
+            cached,
             duplicate_count,
             duplicate_len_sum,
+            errors,
+            interrupted,
         })
     }
 
@@ -128,18 +270,263 @@ impl DupReport {
         skin: &MadSkin,
     ) {
         static MD: &str = r#"
-        I've hashed *${seen}* files and found *${set_count}* sets of duplicates.\
+        ${interrupted
+        **The analysis was interrupted**, only part of the duplicates were found.\
+        }
+        I've found *${seen}* files, fully hashed the *${hashed}* ones which could have a duplicate, and found *${set_count}* sets of duplicates.\
+        ${excluded
+        *${excluded_dirs}* directories and *${excluded_files}* files were excluded by globs.\
+        }
+        ${ignored
+        *${ignored_count}* files and directories were ignored according to ignore files.\
+        }
+        ${size_filtered
+        *${size_filtered_count}* files were ignored because of their size.\
+        }
+        ${too_deep
+        *${too_deep_count}* directories were too deep to be entered.\
+        }
+        ${hard_links
+        *${hard_link_count}* paths are hard links to *${hard_linked_file_count}* files, which are thus already deduplicated (saving **${hard_link_gain}**).\
+        }
+        ${cached
+        *${cached_count}* hashes were taken from the cache.\
+        }
+        ${empty
+        *${empty_count}* empty files were found.\
+        }
+        ${empty_dirs
+        *${empty_dir_count}* empty directories were found.\
+        }
+        ${errors
+        **${error_count}** files or directories couldn't be read.\
+        }
         *${removable_count}* files can be removed to gain **${gain}**.\
         "#;
         let mut expander = OwningTemplateExpander::new();
         expander
                 .set("seen", self.seen)
+                .set("hashed", self.hashed)
                 .set("set_count", self.dups.len())
                 .set("removable_count", self.duplicate_count)
                 .set("gain", file_size::fit_4(self.duplicate_len_sum));
+        if self.interrupted {
+            expander.sub("interrupted");
+        }
+        if self.walk_stats.excluded_dirs + self.walk_stats.excluded_files > 0 {
+            expander.sub("excluded")
+                .set("excluded_dirs", self.walk_stats.excluded_dirs)
+                .set("excluded_files", self.walk_stats.excluded_files);
+        }
+        if self.walk_stats.ignored > 0 {
+            expander.sub("ignored")
+                .set("ignored_count", self.walk_stats.ignored);
+        }
+        if self.walk_stats.size_filtered > 0 {
+            expander.sub("size_filtered")
+                .set("size_filtered_count", self.walk_stats.size_filtered);
+        }
+        if self.walk_stats.too_deep_dirs > 0 {
+            expander.sub("too_deep")
+                .set("too_deep_count", self.walk_stats.too_deep_dirs);
+        }
+        if self.hard_link_count > 0 {
+            expander.sub("hard_links")
+                .set("hard_link_count", self.hard_link_count)
+                .set("hard_linked_file_count", self.hard_linked_file_count)
+                .set("hard_link_gain", file_size::fit_4(self.hard_link_len_sum));
+        }
+        if self.cached > 0 {
+            expander.sub("cached")
+                .set("cached_count", self.cached);
+        }
+        if !self.empty_files.is_empty() {
+            expander.sub("empty")
+                .set("empty_count", self.empty_files.len());
+        }
+        if self.empty_dir_count > 0 {
+            expander.sub("empty_dirs")
+                .set("empty_dir_count", self.empty_dir_count);
+        }
+        if !self.errors.is_empty() {
+            expander.sub("errors")
+                .set("error_count", self.errors.len());
+        }
         skin.print_owning_expander(&expander, &TextTemplate::from(MD));
+        let mount_points = &self.walk_stats.skipped_mount_points;
+        if !mount_points.is_empty() {
+            mad_print_inline!(
+                skin,
+                "*$0* directories on other file systems were skipped:\n",
+                mount_points.len(),
+            );
+            for path in mount_points {
+                mad_print_inline!(skin, " *$0*\n", path.to_string_lossy());
+            }
+        }
+        if self.roots.len() > 1 {
+            self.print_roots_summary(skin);
+        }
+    }
+    fn print_roots_summary(
+        &self,
+        skin: &MadSkin,
+    ) {
+        static MD: &str = r#"
+        |:-|:-:|:-:|
+        |root|files|files with a duplicate|
+        |:-|-:|-:|
+        ${roots
+        |*${path}*${reference}|${seen}|${dup_count}|
+        }
+        |-
+        "#;
+        let mut dup_counts = vec![0; self.roots.len()];
+        for file in self.dups.iter().flat_map(|dup| &dup.files) {
+            dup_counts[file.root_idx] += 1;
+        }
+        let mut expander = OwningTemplateExpander::new();
+        for (root_idx, root) in self.roots.iter().enumerate() {
+            expander.sub("roots")
+                .set("path", root.path.to_string_lossy())
+                .set("reference", if root.reference { " (reference)" } else { "" })
+                .set("seen", self.seen_per_root[root_idx])
+                .set("dup_count", dup_counts[root_idx]);
+        }
+        skin.print_owning_expander(&expander, &TextTemplate::from(MD));
+    }
+    /// list the files and directories which couldn't be read
+    pub fn list_errors(
+        &self,
+        skin: &MadSkin,
+    ) {
+        for error in &self.errors {
+            mad_print_inline!(
+                skin,
+                "*$0* : $1 : $2\n",
+                error.path.to_string_lossy(),
+                error.kind,
+                &error.message,
+            );
+        }
     }
+    /// tell whether there's nothing to propose for removal
     pub fn is_empty(&self) -> bool {
-        self.dups.is_empty()
+        self.dups.is_empty() && self.empty_files.is_empty() && self.empty_dir_count == 0
+    }
+}
+
+/// set the types of the files of a dup set which weren't detected
+/// during the walk. As the files are identical, sniffing one of them
+/// is enough
+fn set_file_types(files: &mut [DupFile], sniff: bool) {
+    if sniff {
+        let file_type = files.iter()
+            .find_map(|file| file.file_type)
+            .or_else(|| sniff_file_type(&files[0].path).ok().flatten());
+        for file in files {
+            file.file_type = file_type;
+        }
+    } else {
+        for file in files {
+            file.file_type = file.path.extension()
+                .and_then(|s| s.to_str())
+                .and_then(FileType::from_extension);
+        }
+    }
+}
+
+/// Compute in parallel a key for every file of the groups of same
+/// length files, and return the new groups of files having the same
+/// length and key, when they contain more than one file.
+///
+/// When `readers` is set, files are read in the order of their
+/// inodes by this number of threads, which is much faster on
+/// rotational disks. Otherwise all threads of the pool read files.
+///
+/// Files whose key can't be computed are dropped, and the errors
+/// added to `errors`. When the user interrupts the analysis, the
+/// remaining files are dropped too.
+fn split_groups<K, F>(
+    groups: Vec<(u64, Vec<DupFile>)>,
+    readers: Option<usize>,
+    errors: &mut Vec<ScanError>,
+    compute_key: F,
+) -> Vec<(u64, Vec<DupFile>)>
+where
+    K: Eq + Hash + Send,
+    F: Fn(&Path, u64) -> Result<K> + Sync,
+{
+    let mut files: Vec<(DupFile, u64)> = groups.into_iter()
+        .flat_map(|(len, files)| files.into_iter().map(move |file| (file, len)))
+        .collect();
+    let compute = |(file, len): &(DupFile, u64)| {
+        if is_interrupted() {
+            None
+        } else {
+            Some(compute_key(&file.path, *len))
+        }
+    };
+    let keys: Vec<Option<Result<K>>> = match readers {
+        None => files.par_iter().map(compute).collect(),
+        Some(readers) => {
+            files.sort_by_key(|(file, _)| file.inode);
+            compute_in_order(&files, readers, compute)
+        }
+    };
+    let mut map: FnvHashMap<(u64, K), Vec<DupFile>> = FnvHashMap::default();
+    for ((file, len), key) in files.into_iter().zip(keys) {
+        let key = match key {
+            Some(key) => key,
+            None => { continue; } // interrupted
+        };
+        match key {
+            Ok(key) => {
+                map.entry((len, key)).or_default().push(file);
+            }
+            Err(e) => {
+                errors.push(ScanError::new(file.path, ScanErrorKind::Hash, e));
+            }
+        }
+    }
+    map.into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .map(|((len, _), files)| (len, files))
+        .collect()
+}
+
+/// Compute the keys of the items with `readers` threads, each one
+/// taking the next item not yet handled, so that items are read
+/// in order.
+fn compute_in_order<T, R, F>(
+    items: &[T],
+    readers: usize,
+    compute: F,
+) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let (s_results, r_results) = channel::unbounded::<(usize, R)>();
+    crossbeam::scope(|scope| {
+        for _ in 0..readers {
+            let s_results = s_results.clone();
+            let (next, compute) = (&next, &compute);
+            scope.spawn(move |_| loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                if idx >= items.len() {
+                    break;
+                }
+                s_results.send((idx, compute(&items[idx]))).unwrap();
+            });
+        }
+    }).unwrap();
+    drop(s_results);
+    let mut results: Vec<Option<R>> = (0..items.len()).map(|_| None).collect();
+    for (idx, result) in r_results.iter() {
+        results[idx] = Some(result);
     }
+    results.into_iter().map(|result| result.unwrap()).collect()
 }
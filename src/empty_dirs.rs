@@ -0,0 +1,44 @@
+use {
+    crate::*,
+    std::{
+        collections::HashSet,
+        fs,
+        path::{Path, PathBuf},
+    },
+};
+
+/// Return the directories which are empty, or would be after the
+/// staged removals, deepest first. The directories already staged
+/// for removal aren't returned.
+///
+/// `dirs` must be sorted deepest first, so that chains of directories
+/// which would only contain empty directories are found.
+pub fn find_empty_dirs<'d>(
+    dirs: &'d [PathBuf],
+    dups: &'d [DupSet],
+    rr: &RemovalReport<'d>,
+) -> Vec<&'d Path> {
+    let mut removed: HashSet<&Path> = rr.staged_removals.iter()
+        .map(|dup_file_ref| dup_file_ref.path(dups))
+        .chain(rr.staged_empty_files.iter().copied())
+        .chain(rr.staged_dir_removals.iter().copied())
+        .collect();
+    let mut empty_dirs = Vec::new();
+    for dir in dirs {
+        if removed.contains(dir.as_path()) {
+            continue;
+        }
+        let mut entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => { continue; }
+        };
+        let will_be_empty = entries.all(|e| {
+            e.is_ok_and(|e| removed.contains(e.path().as_path()))
+        });
+        if will_be_empty {
+            removed.insert(dir);
+            empty_dirs.push(dir.as_path());
+        }
+    }
+    empty_dirs
+}
@@ -1,13 +1,181 @@
 use {
     phf::{phf_set, Set},
+    std::{
+        collections::HashSet,
+        fmt,
+        path::Path,
+        str::FromStr,
+    },
 };
 
+// all extensions are lowercase, as they're compared
+// with lowercased file extensions
+
 static IMAGE_EXTENSIONS: Set<&'static str> = phf_set! {
-    "jpg", "JPG",
-    "jpeg", "JPEG",
-    "png", "PNG",
+    "avif", "bmp", "gif", "heic", "heif", "ico", "jpeg", "jpg", "jxl",
+    "png", "psd", "svg", "tif", "tiff", "webp",
+    // camera RAW formats
+    "3fr", "arw", "cr2", "cr3", "crw", "dng", "erf", "kdc", "mrw", "nef",
+    "nrw", "orf", "pef", "raf", "raw", "rw2", "sr2", "srf", "srw", "x3f",
+};
+
+static VIDEO_EXTENSIONS: Set<&'static str> = phf_set! {
+    "3gp", "avi", "flv", "m2ts", "m4v", "mkv", "mov", "mp4", "mpeg",
+    "mpg", "mts", "ogv", "vob", "webm", "wmv",
+};
+
+static AUDIO_EXTENSIONS: Set<&'static str> = phf_set! {
+    "aac", "aif", "aiff", "alac", "ape", "flac", "m4a", "mid", "midi",
+    "mp3", "oga", "ogg", "opus", "wav", "wma",
+};
+
+static DOCUMENT_EXTENSIONS: Set<&'static str> = phf_set! {
+    "csv", "djvu", "doc", "docx", "epub", "md", "mobi", "odp", "ods",
+    "odt", "pdf", "ppt", "pptx", "rtf", "tex", "txt", "xls", "xlsx",
+};
+
+static ARCHIVE_EXTENSIONS: Set<&'static str> = phf_set! {
+    "7z", "bz2", "cab", "deb", "dmg", "gz", "iso", "jar", "lz", "lzma",
+    "rar", "rpm", "tar", "tbz2", "tgz", "txz", "xz", "zip", "zst",
 };
 
+/// a kind of files, recognized by their extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileType {
+    Image,
+    Video,
+    Audio,
+    Document,
+    Archive,
+}
+
+impl FileType {
+    pub const ALL: &'static [Self] = &[
+        Self::Image,
+        Self::Video,
+        Self::Audio,
+        Self::Document,
+        Self::Archive,
+    ];
+    fn extensions(self) -> &'static Set<&'static str> {
+        match self {
+            Self::Image => &IMAGE_EXTENSIONS,
+            Self::Video => &VIDEO_EXTENSIONS,
+            Self::Audio => &AUDIO_EXTENSIONS,
+            Self::Document => &DOCUMENT_EXTENSIONS,
+            Self::Archive => &ARCHIVE_EXTENSIONS,
+        }
+    }
+    /// tell whether the extension, whatever its case, is one of this type
+    pub fn has_extension(self, ext: &str) -> bool {
+        self.extensions().contains(ext.to_ascii_lowercase().as_str())
+    }
+    /// return the type of files having this extension, if known
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        let ext = ext.to_ascii_lowercase();
+        Self::ALL.iter().copied().find(|t| t.extensions().contains(ext.as_str()))
+    }
+}
+
+impl fmt::Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Image => "image",
+            Self::Video => "video",
+            Self::Audio => "audio",
+            Self::Document => "document",
+            Self::Archive => "archive",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for FileType {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "image" | "images" => Ok(Self::Image),
+            "video" | "videos" => Ok(Self::Video),
+            "audio" => Ok(Self::Audio),
+            "document" | "documents" => Ok(Self::Document),
+            "archive" | "archives" => Ok(Self::Archive),
+            _ => Err(format!(
+                "unknown file type {:?} (expected images, videos, audio, documents or archives)",
+                s,
+            )),
+        }
+    }
+}
+
 pub fn is_image(ext: &str) -> bool {
-    IMAGE_EXTENSIONS.contains(ext)
+    FileType::Image.has_extension(ext)
+}
+
+/// the types or extensions files must have to be analyzed: some file
+/// types and maybe some custom extensions
+#[derive(Debug, Clone, Default)]
+pub struct TypeFilter {
+    types: Vec<FileType>,
+    /// lowercase custom extensions
+    custom: HashSet<String>,
+}
+
+impl TypeFilter {
+    /// build the filter from file types and from custom extensions,
+    /// which may be given as comma separated lists
+    pub fn new(types: &[FileType], custom: &[String]) -> Self {
+        let custom = custom.iter()
+            .flat_map(|list| list.split(','))
+            .map(|ext| ext.trim().trim_start_matches('.').to_ascii_lowercase())
+            .filter(|ext| !ext.is_empty())
+            .collect();
+        Self {
+            types: types.to_vec(),
+            custom,
+        }
+    }
+    /// tell whether all files are accepted
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty() && self.custom.is_empty()
+    }
+    /// tell whether there are file types to check (the custom
+    /// extensions can only be checked with the file names)
+    pub fn has_types(&self) -> bool {
+        !self.types.is_empty()
+    }
+    /// tell whether the file must be analyzed. When its type was detected
+    /// from its content, it's used instead of the one of its extension
+    pub fn accepts(&self, path: &Path, detected_type: Option<FileType>) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let ext = path.extension()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_ascii_lowercase());
+        if let Some(ext) = &ext {
+            if self.custom.contains(ext) {
+                return true;
+            }
+        }
+        match (detected_type, ext) {
+            (Some(file_type), _) => self.types.contains(&file_type),
+            (None, Some(ext)) => self.types.iter().any(|t| t.extensions().contains(ext.as_str())),
+            (None, None) => false,
+        }
+    }
+}
+
+#[test]
+fn test_extension_filter() {
+    let filter = TypeFilter::new(&[FileType::Image], &["blend, .XCF".to_string()]);
+    assert!(filter.accepts(Path::new("a/b.jpg"), None));
+    assert!(filter.accepts(Path::new("a/b.Jpg"), None));
+    assert!(filter.accepts(Path::new("a/b.HEIC"), None));
+    assert!(filter.accepts(Path::new("a/b.xcf"), None));
+    assert!(filter.accepts(Path::new("a/b.blend"), None));
+    assert!(!filter.accepts(Path::new("a/b.mp4"), None));
+    assert!(!filter.accepts(Path::new("a/jpg"), None));
+    assert!(filter.accepts(Path::new("a/jpg"), Some(FileType::Image)));
+    assert!(!filter.accepts(Path::new("a/b.jpg"), Some(FileType::Video)));
+    assert!(TypeFilter::default().accepts(Path::new("a/jpg"), None));
 }
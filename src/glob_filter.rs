@@ -0,0 +1,81 @@
+use {
+    anyhow::Result,
+    globset::{Glob, GlobSet, GlobSetBuilder},
+    std::{
+        ffi::OsString,
+        path::Path,
+    },
+};
+
+/// Include and exclude globs, checked against both the
+/// path and the name of files and directories
+#[derive(Debug, Clone, Default)]
+pub struct GlobFilter {
+    includes: Option<GlobSet>,
+    excludes: Option<GlobSet>,
+}
+
+fn glob_set(patterns: &[String]) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(Some(builder.build()?))
+}
+
+fn is_match(set: &GlobSet, path: &Path) -> bool {
+    set.is_match(path) || path.file_name().is_some_and(|name| set.is_match(name))
+}
+
+impl GlobFilter {
+    pub fn new(includes: &[String], excludes: &[String]) -> Result<Self> {
+        Ok(Self {
+            includes: glob_set(includes)?,
+            excludes: glob_set(excludes)?,
+        })
+    }
+    /// tell whether the directory must not be entered.
+    ///
+    /// Includes globs aren't checked here as they apply to files
+    pub fn excludes_dir(&self, path: &Path) -> bool {
+        match &self.excludes {
+            Some(excludes) => {
+                // the trailing slash makes "**/node_modules/**" match the directory
+                let mut dir_path = OsString::from(path);
+                dir_path.push("/");
+                is_match(excludes, path) || is_match(excludes, Path::new(&dir_path))
+            }
+            None => false,
+        }
+    }
+    /// tell whether the file must be ignored, either because it's
+    /// excluded or because it's not included
+    pub fn excludes_file(&self, path: &Path) -> bool {
+        if let Some(excludes) = &self.excludes {
+            if is_match(excludes, path) {
+                return true;
+            }
+        }
+        match &self.includes {
+            Some(includes) => !is_match(includes, path),
+            None => false,
+        }
+    }
+}
+
+#[test]
+fn test_glob_filter() {
+    let filter = GlobFilter::new(
+        &["*.jpg".to_string()],
+        &["**/node_modules/**".to_string(), "Thumbs.db".to_string(), "*.tmp".to_string()],
+    ).unwrap();
+    assert!(filter.excludes_dir(Path::new("/home/me/dev/node_modules")));
+    assert!(!filter.excludes_dir(Path::new("/home/me/dev/src")));
+    assert!(filter.excludes_file(Path::new("/home/me/Thumbs.db")));
+    assert!(filter.excludes_file(Path::new("/home/me/a.tmp")));
+    assert!(filter.excludes_file(Path::new("/home/me/a.png")));
+    assert!(!filter.excludes_file(Path::new("/home/me/a.jpg")));
+}
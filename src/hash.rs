@@ -3,24 +3,129 @@ use {
     anyhow::Result,
     std::{
         fs::File,
-        io,
+        io::{self, Read, Seek, SeekFrom},
         path::Path,
     },
 };
 
+/// size of the blocks read at the start and at the end of a file
+/// to compute its partial hash
+pub const PARTIAL_HASH_BLOCK_SIZE: u64 = 4 * 1024;
+
+/// size above which a file is memory mapped and hashed by
+/// several threads, when parallel reads are allowed
+pub const PARALLEL_HASH_MIN_SIZE: u64 = 64 * 1024 * 1024;
+
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub struct FileHash {
     hash: blake3::Hash,
 }
 
+/// A hash of the first and last blocks of a file, cheap to compute,
+/// which is enough to tell apart most files of the same size
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct PartialHash {
+    hash: blake3::Hash,
+}
+
 impl FileHash {
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+    /// Compute the hash of the whole file.
+    ///
+    /// When `parallel` is true, big files are hashed with BLAKE3's
+    /// multithreaded mode, which gives the same hash. It shouldn't be
+    /// used on rotational disks, where the file is better read in order.
+    pub fn new<P: AsRef<Path>>(path: P, parallel: bool) -> Result<Self> {
+        let path = path.as_ref();
+        let mut file = File::open(path)?;
+        let mut hasher = blake3::Hasher::new();
+        if parallel && file.metadata()?.len() >= PARALLEL_HASH_MIN_SIZE {
+            hasher.update_mmap_rayon(path)?;
+        } else {
+            io::copy(&mut file, &mut hasher)?;
+        }
+        let hash = hasher.finalize();
+        Ok(Self {
+            hash,
+        })
+    }
+    pub fn as_blake3(&self) -> &blake3::Hash {
+        &self.hash
+    }
+}
+
+impl From<blake3::Hash> for FileHash {
+    fn from(hash: blake3::Hash) -> Self {
+        Self { hash }
+    }
+}
+
+impl PartialHash {
+    /// compute the partial hash of a file whose length is known
+    pub fn new<P: AsRef<Path>>(path: P, len: u64) -> Result<Self> {
         let mut file = File::open(path)?;
         let mut hasher = blake3::Hasher::new();
-        io::copy(&mut file, &mut hasher)?;
+        let mut block = [0; PARTIAL_HASH_BLOCK_SIZE as usize];
+        let head_len = len.min(PARTIAL_HASH_BLOCK_SIZE) as usize;
+        file.read_exact(&mut block[..head_len])?;
+        hasher.update(&block[..head_len]);
+        if len > PARTIAL_HASH_BLOCK_SIZE {
+            let tail_len = (len - PARTIAL_HASH_BLOCK_SIZE).min(PARTIAL_HASH_BLOCK_SIZE);
+            file.seek(SeekFrom::Start(len - tail_len))?;
+            let tail_len = tail_len as usize;
+            file.read_exact(&mut block[..tail_len])?;
+            hasher.update(&block[..tail_len]);
+        }
         let hash = hasher.finalize();
         Ok(Self {
             hash,
         })
     }
 }
+
+/// compare two files byte per byte
+pub fn files_are_identical<P: AsRef<Path>, Q: AsRef<Path>>(a: P, b: Q) -> io::Result<bool> {
+    let mut a = File::open(a)?;
+    let mut b = File::open(b)?;
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+    const BUFFER_SIZE: usize = 64 * 1024;
+    let mut a_buffer = vec![0; BUFFER_SIZE];
+    let mut b_buffer = vec![0; BUFFER_SIZE];
+    loop {
+        let a_len = read_full(&mut a, &mut a_buffer)?;
+        let b_len = read_full(&mut b, &mut b_buffer)?;
+        if a_buffer[..a_len] != b_buffer[..b_len] {
+            return Ok(false);
+        }
+        if a_len < BUFFER_SIZE {
+            return Ok(true);
+        }
+    }
+}
+
+/// read until the buffer is full or the end of the file is reached
+fn read_full(file: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buffer.len() {
+        match file.read(&mut buffer[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok(len)
+}
+
+#[test]
+fn test_parallel_file_hash() {
+    let path = std::env::temp_dir().join(format!("backdown-test-{}", std::process::id()));
+    let content: Vec<u8> = (0..PARALLEL_HASH_MIN_SIZE + 12_345)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    std::fs::write(&path, &content).unwrap();
+    let sequential = FileHash::new(&path, false).unwrap();
+    let parallel = FileHash::new(&path, true).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(sequential, parallel);
+    assert_eq!(sequential.as_blake3(), &blake3::hash(&content));
+}
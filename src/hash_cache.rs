@@ -0,0 +1,220 @@
+use {
+    crate::*,
+    anyhow::Result,
+    fnv::FnvHashMap,
+    std::{
+        fs::{self, File},
+        io::{BufRead, BufReader, BufWriter, Write},
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// entries which weren't used for this number of seconds
+/// are removed from the cache
+const MAX_ENTRY_AGE: u64 = 90 * 24 * 60 * 60;
+
+const CACHE_FILE_NAME: &str = "file-hashes.txt";
+
+/// what identifies a file and its content, as long as it's
+/// not modified
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct CacheKey {
+    dev: u64,
+    ino: u64,
+    len: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    hash: blake3::Hash,
+    /// absolute path of the file when it was hashed, only used
+    /// to prune the cache
+    path: PathBuf,
+    /// date of the last use of the entry, in seconds since the epoch
+    last_seen: u64,
+}
+
+/// A persistent cache of the hashes of files, so that files which
+/// weren't modified since a previous analysis don't have to be read
+/// again.
+///
+/// Files are identified by their device, inode, size and modification
+/// date, so the cache is only used on unix.
+#[derive(Debug)]
+pub struct HashCache {
+    path: PathBuf,
+    entries: FnvHashMap<CacheKey, CacheEntry>,
+    /// entries used or computed during this session
+    updates: Mutex<Vec<(CacheKey, CacheEntry)>>,
+    hits: AtomicUsize,
+    current_dir: PathBuf,
+    now: u64,
+}
+
+impl CacheKey {
+    #[cfg(unix)]
+    fn new(md: &fs::Metadata) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+        let mtime = md.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            dev: md.dev(),
+            ino: md.ino(),
+            len: md.len(),
+            mtime_secs: mtime.as_secs(),
+            mtime_nanos: mtime.subsec_nanos(),
+        })
+    }
+    #[cfg(not(unix))]
+    fn new(_md: &fs::Metadata) -> Option<Self> {
+        None
+    }
+}
+
+impl CacheEntry {
+    /// parse a line of the cache file, which is made of
+    /// "hash dev ino len mtime_secs mtime_nanos last_seen path"
+    fn parse(line: &str) -> Option<(CacheKey, Self)> {
+        let mut tokens = line.splitn(8, ' ');
+        let hash = blake3::Hash::from_hex(tokens.next()?).ok()?;
+        let key = CacheKey {
+            dev: tokens.next()?.parse().ok()?,
+            ino: tokens.next()?.parse().ok()?,
+            len: tokens.next()?.parse().ok()?,
+            mtime_secs: tokens.next()?.parse().ok()?,
+            mtime_nanos: tokens.next()?.parse().ok()?,
+        };
+        let last_seen = tokens.next()?.parse().ok()?;
+        let path = PathBuf::from(tokens.next()?);
+        Some((key, Self { hash, path, last_seen }))
+    }
+}
+
+impl HashCache {
+    /// the path to the cache file, in the standard cache directory
+    /// of the platform (for example ~/.cache/backdown on linux)
+    pub fn default_path() -> Option<PathBuf> {
+        directories::ProjectDirs::from("org", "dystroy", "backdown")
+            .map(|dirs| dirs.cache_dir().join(CACHE_FILE_NAME))
+    }
+    /// Load the cache from its default location.
+    ///
+    /// When `rebuild` is true, the existing entries are ignored (and
+    /// will be overwritten on save).
+    pub fn load(rebuild: bool) -> Result<Self> {
+        let path = match Self::default_path() {
+            Some(path) => path,
+            None => anyhow::bail!("no cache directory found"),
+        };
+        let mut entries = FnvHashMap::default();
+        if !rebuild && path.exists() {
+            let reader = BufReader::new(File::open(&path)?);
+            for line in reader.lines() {
+                match CacheEntry::parse(&line?) {
+                    Some((key, entry)) => { entries.insert(key, entry); }
+                    None => { warn!("invalid line in hash cache"); }
+                }
+            }
+        }
+        info!("{} entries loaded from hash cache {:?}", entries.len(), &path);
+        Ok(Self {
+            path,
+            entries,
+            updates: Mutex::new(Vec::new()),
+            hits: AtomicUsize::new(0),
+            current_dir: std::env::current_dir()?,
+            now: now_secs(),
+        })
+    }
+    /// number of hashes which were found in the cache
+    pub fn hit_count(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+    /// get the hash of the file from the cache if it wasn't modified,
+    /// compute it otherwise (see [FileHash::new] for `parallel`)
+    pub fn file_hash(&self, path: &Path, parallel: bool) -> Result<FileHash> {
+        let key = match CacheKey::new(&fs::metadata(path)?) {
+            Some(key) => key,
+            None => { return FileHash::new(path, parallel); }
+        };
+        let hash = match self.entries.get(&key) {
+            Some(entry) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                FileHash::from(entry.hash)
+            }
+            None => FileHash::new(path, parallel)?,
+        };
+        let entry = CacheEntry {
+            hash: *hash.as_blake3(),
+            path: self.current_dir.join(path),
+            last_seen: self.now,
+        };
+        self.updates.lock().unwrap().push((key, entry));
+        Ok(hash)
+    }
+    /// Write the cache, pruning the stale entries: the ones of files
+    /// in the analyzed roots which were removed or modified, and the
+    /// ones which weren't used for a long time.
+    ///
+    /// Entries of files which weren't hashed during this session (because
+    /// they were filtered out or had a unique size) are kept.
+    pub fn save(self, roots: &[PathBuf]) -> Result<()> {
+        let roots: Vec<PathBuf> = roots.iter()
+            .map(|root| self.current_dir.join(root))
+            .collect();
+        let mut entries = self.entries;
+        entries.retain(|key, entry| {
+            if entry.last_seen + MAX_ENTRY_AGE <= self.now {
+                return false;
+            }
+            if !roots.iter().any(|root| entry.path.starts_with(root)) {
+                return true;
+            }
+            // the file is still there, and not modified
+            fs::metadata(&entry.path).ok()
+                .and_then(|md| CacheKey::new(&md))
+                .is_some_and(|current_key| current_key == *key)
+        });
+        entries.extend(self.updates.into_inner().unwrap());
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        for (key, entry) in &entries {
+            let path = match entry.path.to_str() {
+                Some(path) if !path.contains('\n') => path,
+                _ => { continue; } // such files will just be hashed again
+            };
+            writeln!(
+                writer,
+                "{} {} {} {} {} {} {} {}",
+                entry.hash.to_hex(),
+                key.dev,
+                key.ino,
+                key.len,
+                key.mtime_secs,
+                key.mtime_nanos,
+                entry.last_seen,
+                path,
+            )?;
+        }
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&tmp_path, &self.path)?;
+        info!("{} entries written in hash cache", entries.len());
+        Ok(())
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
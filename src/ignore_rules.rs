@@ -0,0 +1,69 @@
+use {
+    ignore::{
+        gitignore::Gitignore,
+        Match,
+    },
+    std::{
+        path::Path,
+        sync::Arc,
+    },
+};
+
+/// names of the files whose rules are applied when respecting
+/// ignore files, the most prioritary first
+pub const IGNORE_FILE_NAMES: &[&str] = &[".backdownignore", ".ignore", ".gitignore"];
+
+/// The ignore rules applying in a directory: the ones of its own
+/// ignore files, then the ones of its parents (up to the analyzed root)
+#[derive(Debug)]
+pub struct IgnoreRules {
+    parent: Option<Arc<IgnoreRules>>,
+    /// matchers of the directory, the most prioritary first
+    matchers: Vec<Gitignore>,
+}
+
+impl IgnoreRules {
+    /// build the rules of a directory, which are the parent's ones
+    /// when there's no ignore file in this directory
+    pub fn for_dir(
+        dir: &Path,
+        parent: Option<Arc<IgnoreRules>>,
+    ) -> Option<Arc<IgnoreRules>> {
+        let mut matchers = Vec::new();
+        for name in IGNORE_FILE_NAMES {
+            let path = dir.join(name);
+            if !path.is_file() {
+                continue;
+            }
+            let (matcher, err) = Gitignore::new(&path);
+            if let Some(err) = err {
+                warn!("error in ignore file {:?}: {}", path, err);
+            }
+            if !matcher.is_empty() {
+                matchers.push(matcher);
+            }
+        }
+        if matchers.is_empty() {
+            parent
+        } else {
+            Some(Arc::new(Self { parent, matchers }))
+        }
+    }
+    /// tell whether the path is ignored, the deepest rule
+    /// matching the path deciding (so that a negated rule
+    /// can whitelist a path ignored in a parent)
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut rules = Some(self);
+        while let Some(r) = rules {
+            for matcher in &r.matchers {
+                match matcher.matched(path, is_dir) {
+                    Match::Ignore(_) => { return true; }
+                    Match::Whitelist(_) => { return false; }
+                    Match::None => {}
+                }
+            }
+            rules = r.parent.as_deref();
+        }
+        false
+    }
+}
@@ -0,0 +1,81 @@
+use {
+    anyhow::Result,
+    crossbeam::channel::{self, Receiver, RecvTimeoutError},
+    lazy_regex::Lazy,
+    std::{
+        io,
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+        time::Duration,
+    },
+};
+
+/// set when the user hits ctrl-c, until the interruption is handled
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// The lines typed by the user.
+///
+/// They're read in a dedicated thread so that waiting for an answer
+/// can be interrupted.
+static STDIN_LINES: Lazy<Receiver<io::Result<String>>> = Lazy::new(|| {
+    let (s_lines, r_lines) = channel::unbounded();
+    thread::spawn(move || loop {
+        let mut line = String::new();
+        let read = io::stdin().read_line(&mut line);
+        let end = !matches!(read, Ok(n) if n > 0);
+        if s_lines.send(read.map(|_| line)).is_err() || end {
+            break;
+        }
+    });
+    r_lines
+});
+
+/// Install the handler of ctrl-c.
+///
+/// A first ctrl-c only sets a flag, so that the current phase may
+/// be stopped cleanly. A second one, if the first one wasn't handled
+/// yet, quits the application.
+pub fn catch_interrupts() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+    })?;
+    Ok(())
+}
+
+/// whether the user hit ctrl-c and it wasn't handled yet
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// mark the interruption as handled
+pub fn clear_interrupt() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+/// Read a line typed by the user, without its end of line.
+///
+/// Return `None`, and clear the interruption, when the user
+/// hits ctrl-c while it's waited for.
+pub fn read_line() -> io::Result<Option<String>> {
+    loop {
+        match STDIN_LINES.recv_timeout(Duration::from_millis(100)) {
+            Ok(Ok(line)) if !line.is_empty() => {
+                return Ok(Some(line.trim_end().to_string()));
+            }
+            Ok(Ok(_)) | Err(RecvTimeoutError::Disconnected) => {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            Ok(Err(e)) => {
+                return Err(e);
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if is_interrupted() {
+                    clear_interrupt();
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
@@ -3,10 +3,49 @@ use {
     std::{
         fs,
         io::Write,
-        path::PathBuf,
+        path::{Path, PathBuf},
     },
 };
 
+/// Return the path as a JSON string, and whether it had to be escaped.
+///
+/// A path which isn't valid UTF-8 is written with its backslashes
+/// doubled and its invalid bytes as `\xHH`, so that the real bytes
+/// can be found back.
+pub fn path_to_json(path: &Path) -> (String, bool) {
+    match path.to_str() {
+        Some(s) => (s.to_string(), false),
+        None => (escape_non_utf8(path), true),
+    }
+}
+
+#[cfg(unix)]
+fn escape_non_utf8(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+    let mut bytes = path.as_os_str().as_bytes();
+    let mut escaped = String::new();
+    while !bytes.is_empty() {
+        let (valid, invalid_len) = match std::str::from_utf8(bytes) {
+            Ok(valid) => (valid, 0),
+            Err(e) => {
+                let valid = std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap();
+                (valid, e.error_len().unwrap_or(bytes.len() - e.valid_up_to()))
+            }
+        };
+        escaped.push_str(&valid.replace('\\', "\\\\"));
+        bytes = &bytes[valid.len()..];
+        for b in &bytes[..invalid_len] {
+            escaped.push_str(&format!("\\x{:02X}", b));
+        }
+        bytes = &bytes[invalid_len..];
+    }
+    escaped
+}
+#[cfg(not(unix))]
+fn escape_non_utf8(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
 fn available_path(name: &str) -> PathBuf {
     let mut count = 1;
     let ext = "json";
@@ -44,3 +83,12 @@ pub fn write_in_file(
     writeln!(&mut file, "{}", json)?;
     Ok(path)
 }
+
+#[cfg(unix)]
+#[test]
+fn test_path_to_json() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+    assert_eq!(path_to_json(Path::new("a/b\\c.jpg")), ("a/b\\c.jpg".to_string(), false));
+    let path = Path::new(OsStr::from_bytes(b"photos/caf\xE9\\1.jpg"));
+    assert_eq!(path_to_json(path), ("photos/caf\\xE9\\\\1.jpg".to_string(), true));
+}
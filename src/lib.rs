@@ -3,23 +3,47 @@
 pub mod args;
 pub mod ask;
 pub mod dirs;
+pub mod disk;
 pub mod dup;
 pub mod dup_report;
+pub mod empty_dirs;
 pub mod ext;
 pub mod file_pair;
+pub mod glob_filter;
 pub mod hash;
+pub mod hash_cache;
+pub mod ignore_rules;
+pub mod interrupt;
 mod json;
+pub mod magic;
+pub mod progress;
 pub mod removal_report;
+pub mod root;
+pub mod scan_error;
+pub mod scan_options;
+pub mod walker;
 
 pub use {
     args::*,
     ask::*,
     dirs::*,
+    disk::*,
     dup::*,
     dup_report::*,
+    empty_dirs::*,
     file_pair::*,
+    glob_filter::*,
     ext::*,
     hash::*,
+    hash_cache::*,
+    ignore_rules::*,
+    interrupt::*,
     json::*,
+    magic::*,
+    progress::*,
     removal_report::*,
+    root::*,
+    scan_error::*,
+    scan_options::*,
+    walker::*,
 };
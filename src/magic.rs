@@ -0,0 +1,116 @@
+use {
+    crate::*,
+    std::{
+        fs::File,
+        io::{self, Read},
+        path::Path,
+    },
+};
+
+/// number of leading bytes read to recognize the type of a file
+/// (the tar signature is at offset 257)
+const SNIFFED_LEN: u64 = 512;
+
+/// read the first bytes of a file and try to recognize its type.
+///
+/// As some formats are containers (for example docx or epub files are
+/// zip archives), the type given by the extension is preferred when
+/// it's compatible with the detected one.
+pub fn sniff_file_type(path: &Path) -> io::Result<Option<FileType>> {
+    let mut bytes = Vec::new();
+    File::open(path)?.take(SNIFFED_LEN).read_to_end(&mut bytes)?;
+    let detected = file_type_from_magic(&bytes);
+    let from_extension = path.extension()
+        .and_then(|s| s.to_str())
+        .and_then(FileType::from_extension);
+    Ok(match (detected, from_extension) {
+        (Some(FileType::Archive), Some(FileType::Document)) => Some(FileType::Document),
+        (Some(FileType::Audio), Some(FileType::Video)) => Some(FileType::Video),
+        (detected, _) => detected,
+    })
+}
+
+/// recognize the type of a file from its first bytes
+pub fn file_type_from_magic(bytes: &[u8]) -> Option<FileType> {
+    let starts = |magic: &[u8]| bytes.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| {
+        bytes.len() >= offset + magic.len() && &bytes[offset..offset + magic.len()] == magic
+    };
+    if starts(b"\xFF\xD8\xFF")
+        || starts(b"\x89PNG\r\n\x1A\n")
+        || starts(b"GIF87a")
+        || starts(b"GIF89a")
+        || (starts(b"RIFF") && at(8, b"WEBP"))
+        || starts(b"II*\0") // TIFF, and many camera RAW formats
+        || starts(b"MM\0*")
+        || starts(b"8BPS")
+        || starts(b"\xFF\x0A")
+        || starts(b"\0\0\0\x0CJXL ")
+    {
+        return Some(FileType::Image);
+    }
+    if at(4, b"ftyp") {
+        // ISO base media file, the brand tells what it contains
+        return match bytes.get(8..12) {
+            Some(b"heic" | b"heix" | b"hevc" | b"hevx" | b"mif1" | b"msf1" | b"avif" | b"avis" | b"crx ") => {
+                Some(FileType::Image)
+            }
+            Some(b"M4A " | b"M4B " | b"M4P ") => Some(FileType::Audio),
+            Some(_) => Some(FileType::Video),
+            None => None,
+        };
+    }
+    if starts(b"\x1A\x45\xDF\xA3") // matroska and webm
+        || (starts(b"RIFF") && at(8, b"AVI "))
+        || starts(b"\0\0\x01\xBA")
+        || starts(b"\0\0\x01\xB3")
+        || starts(b"FLV")
+        || starts(b"\x30\x26\xB2\x75\x8E\x66\xCF\x11")
+    {
+        return Some(FileType::Video);
+    }
+    if starts(b"ID3")
+        || starts(b"\xFF\xFB")
+        || starts(b"\xFF\xF3")
+        || starts(b"\xFF\xF2")
+        || starts(b"fLaC")
+        || starts(b"OggS")
+        || (starts(b"RIFF") && at(8, b"WAVE"))
+        || (starts(b"FORM") && at(8, b"AIFF"))
+        || starts(b"MThd")
+    {
+        return Some(FileType::Audio);
+    }
+    if starts(b"%PDF-")
+        || starts(b"{\\rtf")
+        || starts(b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1") // old MS Office documents
+        || starts(b"AT&TFORM")
+    {
+        return Some(FileType::Document);
+    }
+    if starts(b"PK\x03\x04")
+        || starts(b"\x1F\x8B")
+        || starts(b"BZh")
+        || starts(b"\xFD7zXZ\0")
+        || starts(b"7z\xBC\xAF\x27\x1C")
+        || starts(b"Rar!\x1A\x07")
+        || starts(b"\x28\xB5\x2F\xFD")
+        || at(257, b"ustar")
+    {
+        return Some(FileType::Archive);
+    }
+    None
+}
+
+#[test]
+fn test_file_type_from_magic() {
+    assert_eq!(file_type_from_magic(b"\xFF\xD8\xFF\xE0\0\x10JFIF"), Some(FileType::Image));
+    assert_eq!(file_type_from_magic(b"\0\0\0\x18ftypheic\0\0\0\0"), Some(FileType::Image));
+    assert_eq!(file_type_from_magic(b"\0\0\0\x18ftypisom\0\0\0\0"), Some(FileType::Video));
+    assert_eq!(file_type_from_magic(b"\0\0\0\x18ftyp"), None);
+    assert_eq!(file_type_from_magic(b"ID3\x04\0"), Some(FileType::Audio));
+    assert_eq!(file_type_from_magic(b"%PDF-1.7"), Some(FileType::Document));
+    assert_eq!(file_type_from_magic(b"PK\x03\x04"), Some(FileType::Archive));
+    assert_eq!(file_type_from_magic(b"just some text"), None);
+    assert_eq!(file_type_from_magic(b""), None);
+}
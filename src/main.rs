@@ -3,6 +3,7 @@
 use {
     backdown::*,
     anyhow::Result,
+    std::path::PathBuf,
     crossterm::style::{Attribute::*, Color::*},
     termimad::*,
 };
@@ -13,18 +14,84 @@ fn run_app() -> Result<()> {
         println!("backdown {}", env!("CARGO_PKG_VERSION"));
         return Ok(());
     }
-    let root = args.path
-        .unwrap_or_else(|| std::env::current_dir().unwrap());
     let skin = make_skin();
-    info!("root: {:?}", &root);
+    catch_interrupts()?;
+    let mut scan_options = ScanOptions::new(&args)?;
+    let mut roots: Vec<Root> = args.paths.iter()
+        .map(|path| Root::new(path.clone(), false))
+        .collect();
+    if roots.is_empty() {
+        roots.push(Root::new(std::env::current_dir()?, false));
+    }
+    for path in &args.reference {
+        roots.push(Root::new(path.clone(), true));
+    }
+    let roots = distinct_roots(roots, &skin)?;
+    info!("roots: {:?}", &roots);
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()?;
+    }
+    if args.readers.is_none() && roots.iter().any(|root| is_rotational(&root.path) == Some(true)) {
+        mad_print_inline!(skin, "Rotational disk detected: files will be read one at a time, in their physical order\n");
+        scan_options.readers = Some(1);
+    }
     skin.print_text("\n# Phase 1) Analysis");
-    mad_print_inline!(skin, "Analyzing directory *$0*...\n", root.to_string_lossy());
+    for root in &roots {
+        if root.reference {
+            mad_print_inline!(skin, "Analyzing reference directory *$0*...\n", root.path.to_string_lossy());
+        } else {
+            mad_print_inline!(skin, "Analyzing directory *$0*...\n", root.path.to_string_lossy());
+        }
+    }
+    let cache = if args.no_cache {
+        None
+    } else {
+        match HashCache::load(args.rebuild_cache) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                warn!("hash cache not loaded: {}", e);
+                None
+            }
+        }
+    };
     let dup_report = time!(
         Info,
         "computing dup sets",
-        DupReport::build(root, args.only_images)?,
+        DupReport::build(roots.clone(), &scan_options, cache.as_ref())?,
     );
+    if dup_report.interrupted {
+        // the interruption was handled, the next ctrl-c will
+        // be for the questions
+        clear_interrupt();
+    }
+    if let Some(cache) = cache {
+        let root_paths: Vec<PathBuf> = roots.iter().map(|root| root.path.clone()).collect();
+        if let Err(e) = cache.save(&root_paths) {
+            mad_print_inline!(skin, "*Failed to save the hash cache*: $0\n", e);
+        }
+    }
     dup_report.print_summary(&skin);
+    if !dup_report.errors.is_empty() {
+        let mut question = Question::new("Do you want to see the files and directories which couldn't be read?");
+        question.add_answer('l', "**L**ist them");
+        question.add_answer('c', "**C**ontinue");
+        question.set_default("c");
+        if ask_question(&question, &skin)?.as_deref() == Some("l") {
+            dup_report.list_errors(&skin);
+        }
+    }
+    if dup_report.interrupted {
+        if dup_report.is_empty() {
+            return Ok(());
+        }
+        let mut question = Question::new("Do you want to continue with the duplicates found so far?");
+        question.add_answer('y', "**Y**es, stage files for removal among them");
+        question.add_answer('n', "**N**o, quit *backdown*");
+        question.set_default("n");
+        if ask_question(&question, &skin)?.as_deref() != Some("y") {
+            return Ok(());
+        }
+    }
     if dup_report.is_empty() {
         println!("There's nothing to remove");
         return Ok(());
@@ -32,10 +99,10 @@ fn run_app() -> Result<()> {
     let dirs_report = time!(
         Info,
         "computing dirs report",
-        DirsReport::compute(&dup_report.dups)?,
+        DirsReport::compute(&dup_report.dups, &scan_options)?,
     );
     skin.print_text("\n# Phase 2) Staging: choose files to remove");
-    let rr = ask_on_dirs(&dirs_report, &dup_report.dups, &skin)?;
+    let rr = ask_on_dirs(&dirs_report, &dup_report, &skin)?;
     if rr.is_empty() || rr.quit {
         return Ok(());
     }
@@ -55,29 +122,29 @@ fn run_app() -> Result<()> {
         #[cfg(unix)]
         question.add_answer('l', "Replace removed files with **l**inks");
         question.add_answer('q', "**Q**uit *backdown*, removing nothing");
-        match question.ask(&skin)?.as_ref() {
-            "s" => {
+        match ask_question(&question, &skin)?.as_deref() {
+            Some("s") => {
                 rr.list_dup_sets(&dup_report.dups, &skin);
             }
-            "j" => {
-                let value = rr.dup_sets_as_json(&dup_report.dups);
+            Some("j") => {
+                let value = rr.dup_sets_as_json(&dup_report.dups, &dup_report.errors);
                 let path = write_in_file("backdown-report", &value)?;
                 mad_print_inline!(skin, "Wrote *$0*\n", path.to_string_lossy());
                 exported = true;
             }
-            "f" => {
+            Some("f") => {
                 rr.list_staged_removals(&dup_report.dups, &skin);
             }
-            "r" => {
-                rr.do_the_removal(&dup_report.dups, &skin)?;
+            Some("r") => {
+                rr.do_the_removal(&dup_report.dups, args.paranoid, &skin)?;
                 break;
             }
-            "l" => {
+            Some("l") => {
                 #[cfg(unix)]
-                rr.replace_staged_with_links(&dup_report.dups, &skin)?;
+                rr.replace_staged_with_links(&dup_report.dups, args.paranoid, &skin)?;
                 break;
             }
-            "q" => {
+            Some("q") | None => {
                 break;
             }
             _ => {} // should not happen
@@ -86,6 +153,45 @@ fn run_app() -> Result<()> {
     Ok(())
 }
 
+/// check the provided directories exist, and remove the ones
+/// whose files would be found twice: the ones inside another root,
+/// unless they're reference roots inside normal roots
+fn distinct_roots(roots: Vec<Root>, skin: &MadSkin) -> Result<Vec<Root>> {
+    let mut canonical_paths = Vec::new();
+    for root in &roots {
+        if !root.path.is_dir() {
+            anyhow::bail!("{:?} isn't a directory", root.path);
+        }
+        canonical_paths.push(root.path.canonicalize()?);
+    }
+    let is_covered_by = |i: usize, j: usize| {
+        let (a, b) = (&roots[i], &roots[j]);
+        if canonical_paths[i] == canonical_paths[j] {
+            // the same directory is kept only once, preferably as reference
+            if a.reference == b.reference { j < i } else { b.reference }
+        } else {
+            canonical_paths[i].starts_with(&canonical_paths[j]) && (b.reference || !a.reference)
+        }
+    };
+    let mut distinct = Vec::new();
+    for i in 0..roots.len() {
+        match (0..roots.len()).find(|&j| j != i && is_covered_by(i, j)) {
+            Some(j) => {
+                mad_print_inline!(
+                    skin,
+                    "*$0* is already in *$1*, it won't be analyzed twice\n",
+                    roots[i].path.to_string_lossy(),
+                    roots[j].path.to_string_lossy(),
+                );
+            }
+            None => {
+                distinct.push(roots[i].clone());
+            }
+        }
+    }
+    Ok(distinct)
+}
+
 fn main() {
     init_cli_log!();
     if let Err(e) = run_app() {
@@ -0,0 +1,160 @@
+use {
+    std::{
+        io::{self, Write},
+        sync::{
+            atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
+    },
+    termimad::crossterm::tty::IsTty,
+};
+
+/// time between two refreshes of the progress line
+const REFRESH_PERIOD: Duration = Duration::from_millis(200);
+
+const WALKING: u8 = 0;
+const PARTIAL_HASHING: u8 = 1;
+const HASHING: u8 = 2;
+
+/// Counters of the analysis, updated by the walker thread
+/// and by the threads of the hashing pool
+#[derive(Debug, Default)]
+pub struct Progress {
+    phase: AtomicU8,
+    pub dirs_walked: AtomicUsize,
+    pub files_found: AtomicUsize,
+    /// number of files to compare by their first and last blocks
+    pub files_to_partially_hash: AtomicUsize,
+    pub files_partially_hashed: AtomicUsize,
+    /// number and total size of the files to fully hash
+    pub files_to_hash: AtomicUsize,
+    pub bytes_to_hash: AtomicU64,
+    pub files_hashed: AtomicUsize,
+    pub bytes_hashed: AtomicU64,
+}
+
+impl Progress {
+    pub fn start_partial_hashing(&self, file_count: usize) {
+        self.files_to_partially_hash.store(file_count, Ordering::Relaxed);
+        self.phase.store(PARTIAL_HASHING, Ordering::Relaxed);
+    }
+    pub fn start_hashing(&self, file_count: usize, len_sum: u64) {
+        self.files_to_hash.store(file_count, Ordering::Relaxed);
+        self.bytes_to_hash.store(len_sum, Ordering::Relaxed);
+        self.phase.store(HASHING, Ordering::Relaxed);
+    }
+    pub fn add_dir(&self) {
+        self.dirs_walked.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn add_file(&self) {
+        self.files_found.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn add_partially_hashed(&self) {
+        self.files_partially_hashed.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn add_hashed(&self, len: u64) {
+        self.files_hashed.fetch_add(1, Ordering::Relaxed);
+        self.bytes_hashed.fetch_add(len, Ordering::Relaxed);
+    }
+    /// the line describing the current state of the analysis.
+    /// `hashing_start` is the time the full hashing started, if it did
+    fn line(&self, hashing_start: Option<Instant>) -> String {
+        let load = |counter: &AtomicUsize| counter.load(Ordering::Relaxed);
+        match self.phase.load(Ordering::Relaxed) {
+            WALKING => format!(
+                "walking: {} directories, {} files found",
+                load(&self.dirs_walked),
+                load(&self.files_found),
+            ),
+            PARTIAL_HASHING => format!(
+                "comparing file ends: {}/{} files",
+                load(&self.files_partially_hashed),
+                load(&self.files_to_partially_hash),
+            ),
+            _ => {
+                let bytes_hashed = self.bytes_hashed.load(Ordering::Relaxed);
+                let bytes_to_hash = self.bytes_to_hash.load(Ordering::Relaxed);
+                let mut line = format!(
+                    "hashing: {}/{} files, {}/{}",
+                    load(&self.files_hashed),
+                    load(&self.files_to_hash),
+                    file_size::fit_4(bytes_hashed),
+                    file_size::fit_4(bytes_to_hash),
+                );
+                let elapsed = hashing_start.map_or(0.0, |start| start.elapsed().as_secs_f64());
+                if elapsed > 1.0 && bytes_hashed > 0 {
+                    let throughput = bytes_hashed as f64 / elapsed;
+                    let remaining = bytes_to_hash.saturating_sub(bytes_hashed) as f64 / throughput;
+                    line.push_str(&format!(
+                        ", {}/s, ETA {}",
+                        file_size::fit_4(throughput as u64),
+                        format_duration(remaining as u64),
+                    ));
+                }
+                line
+            }
+        }
+    }
+}
+
+/// A thread displaying the progress of the analysis on stderr,
+/// on a line which is cleared when the display is stopped.
+///
+/// Nothing is displayed when stderr isn't a terminal.
+pub struct ProgressDisplay {
+    stop: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl ProgressDisplay {
+    pub fn start(progress: Arc<Progress>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = if io::stderr().is_tty() {
+            let stop = Arc::clone(&stop);
+            Some(thread::spawn(move || {
+                let mut hashing_start = None;
+                while !stop.load(Ordering::Relaxed) {
+                    if hashing_start.is_none() && progress.phase.load(Ordering::Relaxed) == HASHING {
+                        hashing_start = Some(Instant::now());
+                    }
+                    let mut stderr = io::stderr().lock();
+                    let _ = write!(stderr, "\r\x1b[2K{}", progress.line(hashing_start));
+                    let _ = stderr.flush();
+                    drop(stderr);
+                    thread::sleep(REFRESH_PERIOD);
+                }
+                let _ = write!(io::stderr(), "\r\x1b[2K");
+            }))
+        } else {
+            None
+        };
+        Self { stop, handle }
+    }
+    /// stop the display and clear the progress line
+    pub fn stop(mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// format a number of seconds in a short human readable way
+fn format_duration(secs: u64) -> String {
+    if secs >= 3600 {
+        format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+#[test]
+fn test_format_duration() {
+    assert_eq!(format_duration(7), "7s");
+    assert_eq!(format_duration(150), "2m30s");
+    assert_eq!(format_duration(3720), "1h02m");
+}
@@ -3,6 +3,7 @@ use {
     minimad::*,
     serde_json::{json, Value},
     std::{
+        cmp::Reverse,
         collections::{HashMap, HashSet},
         fs,
         path::Path,
@@ -15,23 +16,78 @@ pub struct RemovalReport<'d> {
     pub dup_sets_with_staged: HashSet<usize>,
     pub staged_removals: HashSet<DupFileRef>,
     pub staged_dir_removals: Vec<&'d Path>,
+    /// empty files, which aren't in dup sets
+    pub staged_empty_files: Vec<&'d Path>,
     pub len_to_remove: u64,
     pub broken: bool,
     pub quit: bool,
 }
 
 
+/// When paranoid, check the file is really identical to the kept
+/// reference file, byte per byte, and tell the user when it's not.
+///
+/// Return whether the file may be removed
+fn check_identical(
+    path: &Path,
+    reference: &Path,
+    paranoid: bool,
+    skin: &MadSkin,
+) -> bool {
+    if !paranoid {
+        return true;
+    }
+    match files_are_identical(path, reference) {
+        Ok(true) => true,
+        Ok(false) => {
+            mad_print_inline!(
+                skin,
+                " **Not removing** *$0* : it's not identical to *$1*\n",
+                path.to_string_lossy(),
+                reference.to_string_lossy(),
+            );
+            false
+        }
+        Err(e) => {
+            mad_print_inline!(
+                skin,
+                " **Not removing** *$0* : failed to compare it with *$1* : $2\n",
+                path.to_string_lossy(),
+                reference.to_string_lossy(),
+                e,
+            );
+            false
+        }
+    }
+}
+
 impl<'d> RemovalReport<'d> {
 
-    pub fn stage_file(&mut self, dup_file_ref: DupFileRef, dups: &[DupSet]) {
-        self.len_to_remove += dups[dup_file_ref.dup_set_idx].file_len;
+    /// stage a file for removal, unless it's in a reference root.
+    /// Return whether the file was staged
+    pub fn stage_file(&mut self, dup_file_ref: DupFileRef, dups: &[DupSet]) -> bool {
+        if dup_file_ref.is_reference(dups) {
+            warn!("refusing to stage reference file {:?}", dup_file_ref.path(dups));
+            return false;
+        }
+        self.len_to_remove += dup_file_ref.freed_len(dups);
         self.dup_sets_with_staged.insert(dup_file_ref.dup_set_idx);
         self.staged_removals.insert(dup_file_ref);
         // println!("staged {:?}", &dups[dup_file_ref.dup_set_idx].files[dup_file_ref.dup_file_idx].path);
+        true
+    }
+
+    /// the staged file removals, in the order of the dup sets
+    pub fn sorted_staged_removals(&self) -> Vec<DupFileRef> {
+        let mut staged_removals: Vec<DupFileRef> = self.staged_removals.iter().copied().collect();
+        staged_removals.sort();
+        staged_removals
     }
 
     pub fn is_empty(&self) -> bool {
         self.staged_removals.is_empty()
+            && self.staged_empty_files.is_empty()
+            && self.staged_dir_removals.is_empty()
     }
 
     pub fn list_staged_removals(
@@ -39,15 +95,17 @@ impl<'d> RemovalReport<'d> {
         dups: &[DupSet],
         skin: &MadSkin,
     ) {
-        mad_print_inline!(
-            skin,
-            "**$0** files planned for removal for a total size of **$1**:\n",
-            self.staged_removals.len(),
-            file_size::fit_4(self.len_to_remove),
-        );
-        for (idx, file_ref) in self.staged_removals.iter().enumerate() {
+        if !self.staged_removals.is_empty() {
+            mad_print_inline!(
+                skin,
+                "**$0** files planned for removal for a total size of **$1**:\n",
+                self.staged_removals.len(),
+                file_size::fit_4(self.len_to_remove),
+            );
+        }
+        for (idx, file_ref) in self.sorted_staged_removals().into_iter().enumerate() {
             let path = file_ref.path(dups);
-            let size = dups[file_ref.dup_set_idx].file_len;
+            let size = file_ref.freed_len(dups);
             mad_print_inline!(
                 skin,
                 "#$0 : *$1* (**$2**)\n",
@@ -56,39 +114,87 @@ impl<'d> RemovalReport<'d> {
                 file_size::fit_4(size),
             );
         }
+        if !self.staged_empty_files.is_empty() {
+            mad_print_inline!(
+                skin,
+                "**$0** empty files planned for removal:\n",
+                self.staged_empty_files.len(),
+            );
+            for (idx, path) in self.staged_empty_files.iter().enumerate() {
+                mad_print_inline!(skin, "#$0 : *$1*\n", idx + 1, path.to_string_lossy());
+            }
+        }
+        if !self.staged_dir_removals.is_empty() {
+            mad_print_inline!(
+                skin,
+                "**$0** directories planned for removal:\n",
+                self.staged_dir_removals.len(),
+            );
+            for (idx, path) in self.staged_dir_removals.iter().enumerate() {
+                mad_print_inline!(skin, "#$0 : *$1*\n", idx + 1, path.to_string_lossy());
+            }
+        }
     }
 
-    /// write the report as a JSON file
+    /// build the JSON report, with the touched dup sets, the empty
+    /// files staged for removal and the errors encountered during
+    /// the analysis
     pub fn dup_sets_as_json(
         &self,
         dups: &[DupSet],
+        errors: &[ScanError],
     ) -> Value {
-        json!({
+        let mut empty_files = Vec::new();
+        let mut escaped_empty_files = Vec::new();
+        for path in &self.staged_empty_files {
+            let (path, escaped) = path_to_json(path);
+            if escaped {
+                escaped_empty_files.push(path.clone());
+            }
+            empty_files.push(path);
+        }
+        let mut value = json!({
             "len_to_remove": self.len_to_remove,
+            "empty_files": empty_files,
+            "errors": errors.iter().map(ScanError::as_json).collect::<Vec<Value>>(),
             "dup_sets": dups.iter().enumerate()
                 .filter_map(|(dup_set_idx, dup_set)| {
                     if !self.dup_sets_with_staged.contains(&dup_set_idx) {
                         return None;
                     }
-                    Some(json!({
+                    let mut files = HashMap::new();
+                    let mut escaped_files = Vec::new();
+                    for (dup_file_idx, file) in dup_set.files.iter().enumerate() {
+                        let file_ref = DupFileRef { dup_set_idx, dup_file_idx };
+                        let action = if self.staged_removals.contains(&file_ref) {
+                            "remove"
+                        } else {
+                            "keep"
+                        };
+                        let (path, escaped) = path_to_json(&file.path);
+                        if escaped {
+                            escaped_files.push(path.clone());
+                        }
+                        files.insert(path, action);
+                    }
+                    let mut value = json!({
                         "file_len": dup_set.file_len,
-                        "files": dup_set.files.iter()
-                            .enumerate()
-                            .map(|(dup_file_idx, file)| {
-                                let file = file.path.to_string_lossy().to_string();
-                                let file_ref = DupFileRef { dup_set_idx, dup_file_idx };
-                                let action = if self.staged_removals.contains(&file_ref) {
-                                    "remove"
-                                } else {
-                                    "keep"
-                                };
-                                (file, action)
-                            })
-                            .collect::<HashMap<String, &'static str>>()
-                    }))
+                        "file_type": dup_set.file_type().map(|t| t.to_string()),
+                        "files": files,
+                    });
+                    // the paths which aren't valid UTF-8 are listed, so
+                    // that they can be unescaped
+                    if !escaped_files.is_empty() {
+                        value["escaped_files"] = json!(escaped_files);
+                    }
+                    Some(value)
                 })
                 .collect::<Vec<Value>>(),
-        })
+        });
+        if !escaped_empty_files.is_empty() {
+            value["escaped_empty_files"] = json!(escaped_empty_files);
+        }
+        value
     }
 
     pub fn list_dup_sets(
@@ -98,7 +204,7 @@ impl<'d> RemovalReport<'d> {
     ) {
         static MD: &str = r#"
         |:-|:-|
-        |Set #*${set_num}* : each file is **${file_len}**|action|
+        |Set #*${set_num}* : each ${file_type}file is **${file_len}**|action|
         |:-|:-:|
         ${files
         |${path}|**${action}**|
@@ -113,7 +219,11 @@ impl<'d> RemovalReport<'d> {
             let mut expander = OwningTemplateExpander::new();
             expander
                 .set("set_num", dup_set_idx + 1)
-                .set("file_len", file_size::fit_4(dup_set.file_len));
+                .set("file_len", file_size::fit_4(dup_set.file_len))
+                .set(
+                    "file_type",
+                    dup_set.file_type().map_or_else(String::new, |t| format!("{} ", t)),
+                );
             for (dup_file_idx, file) in dup_set.files.iter().enumerate() {
                 let file_ref = DupFileRef { dup_set_idx, dup_file_idx };
                 expander.sub("files")
@@ -135,31 +245,84 @@ impl<'d> RemovalReport<'d> {
     /// in a set of identical files. But if I change those algorithms
     /// and make them more complex, I may make an error. So this
     /// function will check there's at least one kept file in each
-    /// touched set, and will raise an error if a set is totally
-    /// emptied.
+    /// touched set (a file of a reference root is always kept), and
+    /// will raise an error if a set is totally emptied or if a file of
+    /// a reference root is staged.
     /// This *must* be called just before starting the real removals.
     pub fn check_no_emptied_set(
         &self,
         dups: &[DupSet],
     ) -> anyhow::Result<()> {
         for (dup_set_idx, dup_set) in dups.iter().enumerate() {
-            let mut staged_count = 0;
-            for dup_file_idx in 0..dup_set.files.len() {
+            let mut kept_count = 0;
+            for (dup_file_idx, file) in dup_set.files.iter().enumerate() {
                 if self.staged_removals.contains(&DupFileRef{ dup_set_idx, dup_file_idx }) {
-                    staged_count += 1;
+                    if file.reference {
+                        anyhow::bail!("We staged a reference file for removal! Abort!");
+                    }
+                } else {
+                    kept_count += 1;
                 }
             }
-            if staged_count >= dup_set.files.len() {
+            if kept_count == 0 {
                 anyhow::bail!("We staged all files in set for removal! Abort!");
             }
         }
         Ok(())
     }
 
+    /// remove the staged empty files, checking they're still empty,
+    /// and return the number of removed ones
+    fn remove_empty_files(&self, skin: &MadSkin) -> usize {
+        let mut removed_count = 0;
+        for path in &self.staged_empty_files {
+            match fs::symlink_metadata(path) {
+                Ok(md) if md.is_file() && md.len() == 0 => {}
+                Ok(_) => {
+                    mad_print_inline!(skin, " **Not removing** *$0* : it's not empty anymore\n", path.to_string_lossy());
+                    continue;
+                }
+                Err(e) => {
+                    mad_print_inline!(skin, " Failed to remove *$0* : $1\n", path.to_string_lossy(), e);
+                    continue;
+                }
+            }
+            match fs::remove_file(path) {
+                Ok(()) => {
+                    removed_count += 1;
+                }
+                Err(e) => {
+                    mad_print_inline!(skin, " Failed to remove *$0* : $1\n", path.to_string_lossy(), e);
+                }
+            }
+        }
+        removed_count
+    }
+
+    /// remove the staged directories, deepest first so that a directory
+    /// is empty when its turn comes. Directories which aren't empty
+    /// aren't removed
+    fn remove_staged_dirs(&self, skin: &MadSkin) {
+        let mut dirs = self.staged_dir_removals.clone();
+        dirs.sort_by_key(|dir| Reverse(dir.components().count()));
+        for path in &dirs {
+            debug!("removing {:?}", path);
+            if let Err(e) = fs::remove_dir(path) {
+                mad_print_inline!(
+                    skin,
+                    " Failed to remove directory *$0* : $1\n",
+                    path.to_string_lossy(),
+                    e,
+                );
+            }
+        }
+    }
+
     #[cfg(unix)]
     pub fn replace_staged_with_links(
         &self,
         dups: &[DupSet],
+        paranoid: bool,
         skin: &MadSkin,
     ) -> anyhow::Result<()> {
         use std::os::unix::fs::symlink;
@@ -169,7 +332,7 @@ impl<'d> RemovalReport<'d> {
         let mut removed_len = 0;
         let mut removed_count = 0;
         // file removals
-        for dup_file_ref in &self.staged_removals {
+        for dup_file_ref in self.sorted_staged_removals() {
             let dup_set = &dups[dup_file_ref.dup_set_idx];
             let path = dup_file_ref.path(dups);
             let link_destination = match reference_file(dup_file_ref.dup_set_idx, dup_set, &self.staged_removals) {
@@ -178,11 +341,14 @@ impl<'d> RemovalReport<'d> {
                     anyhow::bail!("unexpected lack of kept file in dup set");
                 }
             };
+            if !check_identical(path, link_destination, paranoid, skin) {
+                continue;
+            }
             let link_destination = link_destination.canonicalize()?;
             match fs::remove_file(path) {
                 Ok(()) => {
                     removed_count += 1;
-                    removed_len += dups[dup_file_ref.dup_set_idx].file_len;
+                    removed_len += dup_file_ref.freed_len(dups);
                     match symlink(&link_destination, path) {
                         Ok(()) => {
                             // println!("link {:?} -> {:?}", path, link_destination);
@@ -208,6 +374,11 @@ impl<'d> RemovalReport<'d> {
                 }
             }
         }
+        // empty files can't be replaced with links to identical files
+        removed_count += self.remove_empty_files(skin);
+        // directories only containing replaced files aren't empty
+        // anymore, but the other ones can be removed
+        self.remove_staged_dirs(skin);
         mad_print_inline!(
             skin,
             "Removed *$0* files with a total size of **$1**\n",
@@ -220,6 +391,7 @@ impl<'d> RemovalReport<'d> {
     pub fn do_the_removal(
         &self,
         dups: &[DupSet],
+        paranoid: bool,
         skin: &MadSkin,
     ) -> anyhow::Result<()> {
         self.check_no_emptied_set(dups)?;
@@ -228,12 +400,24 @@ impl<'d> RemovalReport<'d> {
         let mut removed_len = 0;
         let mut removed_count = 0;
         // file removals
-        for dup_file_ref in &self.staged_removals {
+        for dup_file_ref in self.sorted_staged_removals() {
             let path = dup_file_ref.path(dups);
+            if paranoid {
+                let dup_set = &dups[dup_file_ref.dup_set_idx];
+                let reference = match reference_file(dup_file_ref.dup_set_idx, dup_set, &self.staged_removals) {
+                    Some(p) => p,
+                    None => {
+                        anyhow::bail!("unexpected lack of kept file in dup set");
+                    }
+                };
+                if !check_identical(path, reference, paranoid, skin) {
+                    continue;
+                }
+            }
             match fs::remove_file(path) {
                 Ok(()) => {
                     removed_count += 1;
-                    removed_len += dups[dup_file_ref.dup_set_idx].file_len;
+                    removed_len += dup_file_ref.freed_len(dups);
                 }
                 Err(e) => {
                     mad_print_inline!(
@@ -245,18 +429,8 @@ impl<'d> RemovalReport<'d> {
                 }
             }
         }
-        // directory removals
-        for path in &self.staged_dir_removals {
-            debug!("removing {:?}", path);
-            if let Err(e) = fs::remove_dir(path) {
-                mad_print_inline!(
-                    skin,
-                    " Failed to remove directory *$1* : $2\n",
-                    path.to_string_lossy(),
-                    e,
-                );
-            }
-        }
+        removed_count += self.remove_empty_files(skin);
+        self.remove_staged_dirs(skin);
         mad_print_inline!(
             skin,
             "Removed *$0* files with a total size of **$1**\n",
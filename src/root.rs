@@ -0,0 +1,50 @@
+use {
+    std::{
+        collections::HashSet,
+        path::PathBuf,
+    },
+};
+
+/// a directory to analyze
+#[derive(Debug, Clone)]
+pub struct Root {
+    pub path: PathBuf,
+    /// whether the files of this root must never be removed
+    pub reference: bool,
+}
+
+impl Root {
+    pub fn new(path: PathBuf, reference: bool) -> Self {
+        Self { path, reference }
+    }
+}
+
+/// return the paths, as they'll be found by the walker, of the reference
+/// roots which are inside other roots, so that their files aren't found
+/// twice and with the wrong status
+pub fn nested_reference_dirs(roots: &[Root]) -> HashSet<PathBuf> {
+    let mut dirs = HashSet::new();
+    let canonical_paths: Vec<Option<PathBuf>> = roots.iter()
+        .map(|root| root.path.canonicalize().ok())
+        .collect();
+    for (i, reference_root) in roots.iter().enumerate() {
+        if !reference_root.reference {
+            continue;
+        }
+        let reference_path = match &canonical_paths[i] {
+            Some(path) => path,
+            None => { continue; }
+        };
+        for (j, root) in roots.iter().enumerate() {
+            if root.reference {
+                continue;
+            }
+            if let Some(root_path) = &canonical_paths[j] {
+                if let Ok(sub_path) = reference_path.strip_prefix(root_path) {
+                    dirs.insert(root.path.join(sub_path));
+                }
+            }
+        }
+    }
+    dirs
+}
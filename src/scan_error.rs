@@ -0,0 +1,69 @@
+use {
+    crate::*,
+    serde_json::{json, Value},
+    std::{
+        fmt,
+        path::PathBuf,
+    },
+};
+
+/// what was being done when a scan error occurred
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScanErrorKind {
+    /// listing the entries of a directory
+    ReadDir,
+    /// reading the metadata of a file or directory
+    Metadata,
+    /// reading the content of a file to hash it
+    Hash,
+}
+
+/// an error preventing a file or directory from being analyzed
+#[derive(Debug, Clone)]
+pub struct ScanError {
+    pub path: PathBuf,
+    pub kind: ScanErrorKind,
+    pub message: String,
+}
+
+impl ScanErrorKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::ReadDir => "read_dir",
+            Self::Metadata => "metadata",
+            Self::Hash => "hash",
+        }
+    }
+}
+
+impl fmt::Display for ScanErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::ReadDir => "can't list directory",
+            Self::Metadata => "can't read metadata",
+            Self::Hash => "can't read file",
+        })
+    }
+}
+
+impl ScanError {
+    pub fn new<E: fmt::Display>(path: PathBuf, kind: ScanErrorKind, error: E) -> Self {
+        Self {
+            path,
+            kind,
+            message: error.to_string(),
+        }
+    }
+    pub fn as_json(&self) -> Value {
+        let (path, escaped) = path_to_json(&self.path);
+        let mut value = json!({
+            "path": path,
+            "kind": self.kind.as_str(),
+            "error": self.message,
+        });
+        if escaped {
+            value["escaped_path"] = json!(true);
+        }
+        value
+    }
+}
@@ -0,0 +1,77 @@
+use {
+    crate::*,
+    anyhow::Result,
+};
+
+/// What files must be looked at during the analysis
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// the types or extensions of the files to analyze
+    pub types: TypeFilter,
+    /// whether to recognize the types of files by their first
+    /// bytes instead of their extension
+    pub sniff: bool,
+    pub globs: GlobFilter,
+    /// whether to skip the files and directories whose name starts with a dot
+    pub skip_hidden: bool,
+    /// whether to respect .gitignore, .ignore and .backdownignore files
+    pub respect_ignore_files: bool,
+    /// whether to stay on the file system of each root
+    pub one_file_system: bool,
+    /// files smaller than this size are ignored
+    pub min_size: Option<u64>,
+    /// files bigger than this size are ignored
+    pub max_size: Option<u64>,
+    /// files less deep than this are ignored, the files directly
+    /// in a root being at depth 1
+    pub min_depth: Option<usize>,
+    /// files deeper than this are ignored, and directories
+    /// aren't entered
+    pub max_depth: Option<usize>,
+    /// when set, the number of files read at the same time, in
+    /// the order of their inodes. Otherwise files are read by
+    /// all the threads of the pool, in no particular order
+    pub readers: Option<usize>,
+}
+
+impl ScanOptions {
+    pub fn new(args: &Args) -> Result<Self> {
+        if let (Some(min), Some(max)) = (args.min_depth, args.max_depth) {
+            if min > max {
+                anyhow::bail!("the minimal depth can't be greater than the maximal one");
+            }
+        }
+        let mut types = args.types.clone();
+        if args.only_images && !types.contains(&FileType::Image) {
+            types.push(FileType::Image);
+        }
+        Ok(Self {
+            types: TypeFilter::new(&types, &args.ext),
+            sniff: args.sniff,
+            globs: GlobFilter::new(&args.include, &args.exclude)?,
+            skip_hidden: !args.hidden,
+            respect_ignore_files: args.gitignore,
+            one_file_system: args.one_file_system,
+            min_size: args.min_size,
+            max_size: args.max_size,
+            min_depth: args.min_depth,
+            max_depth: args.max_depth,
+            readers: args.readers.filter(|&n| n > 0),
+        })
+    }
+    /// tell whether a file of this size must be analyzed
+    pub fn accepts_size(&self, len: u64) -> bool {
+        self.min_size.is_none_or(|min| len >= min)
+            && self.max_size.is_none_or(|max| len <= max)
+    }
+    /// tell whether a file at this depth must be analyzed
+    pub fn accepts_file_depth(&self, depth: usize) -> bool {
+        self.min_depth.is_none_or(|min| depth >= min)
+            && self.max_depth.is_none_or(|max| depth <= max)
+    }
+    /// tell whether a directory at this depth must be entered,
+    /// that is whether its files would not be too deep
+    pub fn enters_dir_depth(&self, depth: usize) -> bool {
+        self.max_depth.is_none_or(|max| depth < max)
+    }
+}
@@ -0,0 +1,255 @@
+use {
+    crate::*,
+    crossbeam::channel::Sender,
+    std::{
+        collections::HashSet,
+        fs,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    },
+};
+
+/// the device and inode of a file, which identify it
+/// whatever its path
+pub type InodeId = (u64, u64);
+
+/// a file found by the walker, with its length and, when it
+/// has several hard links, the id of its inode
+pub type FoundFile = (DupFile, u64, Option<InodeId>);
+
+/// what the walk produced, apart from the found files
+#[derive(Debug, Default)]
+pub struct WalkResult {
+    pub stats: WalkStats,
+    pub errors: Vec<ScanError>,
+    /// the directories which may be removed, with their number of entries
+    pub removable_dirs: Vec<(PathBuf, usize)>,
+}
+
+/// Walks the roots, sending the files which pass the filters
+/// of the scan options into a channel.
+///
+/// Directories are walked in parallel, each one in its own task
+/// of the rayon pool, so that idle threads steal the pending ones.
+pub struct Walker<'w> {
+    options: &'w ScanOptions,
+    roots: &'w [Root],
+    root_devices: Vec<Option<u64>>,
+    /// reference roots inside other roots are only walked as reference roots
+    nested_reference_dirs: HashSet<PathBuf>,
+    progress: &'w Progress,
+    s_files: Sender<FoundFile>,
+    result: Mutex<WalkResult>,
+}
+
+impl<'w> Walker<'w> {
+    pub fn new(
+        roots: &'w [Root],
+        options: &'w ScanOptions,
+        progress: &'w Progress,
+        s_files: Sender<FoundFile>,
+    ) -> Self {
+        let root_devices = roots.iter()
+            .map(|root| fs::metadata(&root.path).ok().and_then(|md| device_id(&md)))
+            .collect();
+        Self {
+            options,
+            roots,
+            root_devices,
+            nested_reference_dirs: nested_reference_dirs(roots),
+            progress,
+            s_files,
+            result: Mutex::new(WalkResult::default()),
+        }
+    }
+
+    /// Walk all roots and return what isn't a found file.
+    ///
+    /// When the number of readers is limited, so is the number
+    /// of threads walking directories.
+    pub fn walk(self) -> WalkResult {
+        let pool = self.options.readers.and_then(|readers| {
+            rayon::ThreadPoolBuilder::new().num_threads(readers).build().ok()
+        });
+        match pool {
+            Some(pool) => pool.scope(|scope| self.walk_roots(scope)),
+            None => rayon::scope(|scope| self.walk_roots(scope)),
+        }
+        // directories are walked in no particular order
+        let mut result = self.result.into_inner().unwrap();
+        result.stats.skipped_mount_points.sort();
+        result
+    }
+
+    fn walk_roots<'s>(&'s self, scope: &rayon::Scope<'s>) {
+        for (root_idx, root) in self.roots.iter().enumerate() {
+            let dir = root.path.clone();
+            scope.spawn(move |scope| self.walk_dir(scope, root_idx, dir, 0, None));
+        }
+    }
+
+    fn walk_dir<'s>(
+        &'s self,
+        scope: &rayon::Scope<'s>,
+        root_idx: usize,
+        dir: PathBuf,
+        dir_depth: usize,
+        parent_rules: Option<Arc<IgnoreRules>>,
+    ) {
+        if is_interrupted() {
+            return;
+        }
+        let options = self.options;
+        let root = &self.roots[root_idx];
+        self.progress.add_dir();
+        let ignore_rules = if options.respect_ignore_files {
+            IgnoreRules::for_dir(&dir, parent_rules)
+        } else {
+            None
+        };
+        // what's found in this directory, merged at the end in the result
+        let mut result = WalkResult::default();
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                result.errors.push(ScanError::new(dir, ScanErrorKind::ReadDir, e));
+                self.merge(result);
+                return;
+            }
+        };
+        let mut entry_count = 0;
+        for e in entries {
+            entry_count += 1;
+            let e = match e {
+                Ok(e) => e,
+                Err(e) => {
+                    result.errors.push(ScanError::new(dir.clone(), ScanErrorKind::ReadDir, e));
+                    continue;
+                }
+            };
+            let path = e.path();
+            if options.skip_hidden && is_hidden(&e.file_name()) {
+                continue;
+            }
+            let md = match path.symlink_metadata() {
+                Ok(md) => md,
+                Err(e) => {
+                    result.errors.push(ScanError::new(path, ScanErrorKind::Metadata, e));
+                    continue;
+                }
+            };
+            if let Some(rules) = &ignore_rules {
+                if rules.is_ignored(&path, md.is_dir()) {
+                    result.stats.ignored += 1;
+                    continue;
+                }
+            }
+            if md.is_dir() {
+                if self.nested_reference_dirs.contains(&path) {
+                    continue;
+                }
+                if options.globs.excludes_dir(&path) {
+                    result.stats.excluded_dirs += 1;
+                    continue;
+                }
+                if options.one_file_system && device_id(&md) != self.root_devices[root_idx] {
+                    result.stats.skipped_mount_points.push(path);
+                    continue;
+                }
+                if options.enters_dir_depth(dir_depth + 1) {
+                    let rules = ignore_rules.clone();
+                    scope.spawn(move |scope| {
+                        self.walk_dir(scope, root_idx, path, dir_depth + 1, rules);
+                    });
+                } else {
+                    result.stats.too_deep_dirs += 1;
+                }
+                continue;
+            }
+            if md.is_file() {
+                if !options.accepts_file_depth(dir_depth + 1) {
+                    continue;
+                }
+                if options.globs.excludes_file(&path) {
+                    result.stats.excluded_files += 1;
+                    continue;
+                }
+                if !options.accepts_size(md.len()) {
+                    result.stats.size_filtered += 1;
+                    continue;
+                }
+                // sniffing reads the file, so it comes after the cheap checks
+                let detected_type = if options.sniff && options.types.has_types() {
+                    sniff_file_type(&path).ok().flatten()
+                } else {
+                    None
+                };
+                if !options.types.accepts(&path, detected_type) {
+                    continue;
+                }
+                let mut file = DupFile::new(path, root_idx, root.reference, dir_depth + 1);
+                file.file_type = detected_type;
+                file.inode = inode_number(&md);
+                let inode_id = linked_inode_id(&md);
+                file.has_other_links = inode_id.is_some();
+                self.progress.add_file();
+                self.s_files.send((file, md.len(), inode_id)).unwrap();
+            }
+        }
+        if !root.reference && dir != root.path {
+            result.removable_dirs.push((dir, entry_count));
+        }
+        self.merge(result);
+    }
+
+    fn merge(&self, dir_result: WalkResult) {
+        let mut result = self.result.lock().unwrap();
+        let stats = &mut result.stats;
+        stats.excluded_dirs += dir_result.stats.excluded_dirs;
+        stats.excluded_files += dir_result.stats.excluded_files;
+        stats.ignored += dir_result.stats.ignored;
+        stats.size_filtered += dir_result.stats.size_filtered;
+        stats.too_deep_dirs += dir_result.stats.too_deep_dirs;
+        stats.skipped_mount_points.extend(dir_result.stats.skipped_mount_points);
+        result.errors.extend(dir_result.errors);
+        result.removable_dirs.extend(dir_result.removable_dirs);
+    }
+}
+
+/// return the id of the inode of the file when it has
+/// several hard links
+#[cfg(unix)]
+fn linked_inode_id(md: &fs::Metadata) -> Option<InodeId> {
+    use std::os::unix::fs::MetadataExt;
+    if md.nlink() > 1 {
+        Some((md.dev(), md.ino()))
+    } else {
+        None
+    }
+}
+#[cfg(not(unix))]
+fn linked_inode_id(_md: &fs::Metadata) -> Option<InodeId> {
+    None
+}
+
+/// return the inode number of the file
+#[cfg(unix)]
+fn inode_number(md: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    md.ino()
+}
+#[cfg(not(unix))]
+fn inode_number(_md: &fs::Metadata) -> u64 {
+    0
+}
+
+/// return the id of the device the file is on
+#[cfg(unix)]
+fn device_id(md: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(md.dev())
+}
+#[cfg(not(unix))]
+fn device_id(_md: &fs::Metadata) -> Option<u64> {
+    None
+}